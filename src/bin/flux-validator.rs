@@ -11,22 +11,34 @@
 //! Validates that a flux repo will not cause issues when deployed using flux.
 //!
 //! Checks for:
-//! 1. Duplicate names. Only checks deployments.
+//! 1. Duplicate resources, identified by api group, kind, namespace and name.
 //! 2. KMS keys used. Will only return the kms keys used.
 //!   * Can also rotate kms keys using sops.
 //!
+//! Each is a subcommand, `check`, `keys` and `rotate`. Without a subcommand everything is done at
+//! once, like before there were subcommands.
+//!
+//! ### Exit codes
+//! * 0: nothing was found at or above the `--fail-on` severity.
+//! * 1: findings at or above the `--fail-on` severity.
+//! * 2: the tool failed, e.g. bad arguments or a file failed to rotate.
+//!
 //! ### Future plans
 //! 1. Flags any references to other clusters
 //!    * Useful when copying form one cluster to another
 
-use clap::{ArgGroup, CommandFactory, Parser};
-use clap_complete::{generate, Generator, Shell};
-use eyre::{eyre, Result};
-use futures::future::{try_join, try_join3};
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum, ValueHint};
+use clap_complete::{generate, Shell};
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use eyre::{eyre, Result, WrapErr};
+use futures::future::try_join;
 use libs::flux::*;
 use std::{
-    collections::{HashMap, HashSet},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 use termtree::Tree;
 
@@ -41,7 +53,18 @@ use termtree::Tree;
         .args(&["rotate"])
         .requires_all(&[ "kms-arn", "dir"])
 ))]
+#[clap(group(
+    ArgGroup::new("rotation")
+        .args(&["from", "from-regex", "dry-run", "write-plan"])
+        .multiple(true)
+        .requires("rotate")
+))]
+#[clap(group(ArgGroup::new("plan").args(&["apply-plan"]).conflicts_with("rotate")))]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Rotate the KMS key
     #[clap(short, long)]
     rotate: bool,
@@ -50,81 +73,817 @@ struct Args {
     #[clap(long = "kms", value_parser, env = "SOPS_KMS_ARN")]
     kms_arn: Option<String>,
 
+    #[clap(flatten)]
+    rotation: RotationArgs,
+
     /// The directory to check.
+    #[clap(value_parser, value_hint = ValueHint::DirPath)]
     dir: Option<PathBuf>,
 
+    #[clap(flatten)]
+    scan: ScanArgs,
+
+    /// Exit with an error when there are findings of this severity or worse. Defaults to error
+    #[clap(long, value_parser)]
+    fail_on: Option<FailOn>,
+
+    #[clap(flatten)]
+    baseline: BaselineArgs,
+
     /// Generate shell completion
     #[clap(short, long)]
     gen: Option<Shell>,
 }
 
-// Needs to be improved. Right now it is broken and doesn't complete file paths. :(
-fn print_completions<G: Generator>(gen: G, cmd: &mut clap::App) {
-    generate(gen, cmd, cmd.get_name().to_string(), &mut std::io::stdout());
+/// Without a subcommand, the flags above do everything at once like they always did.
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Check for duplicate resources, unencrypted secrets and files that can't be parsed
+    Check(CheckArgs),
+    /// List the keys used by sops and the files encrypted with them
+    Keys(KeysArgs),
+    /// Encrypt the sops files with a new KMS key
+    Rotate(RotateArgs),
+    /// Generate shell completions
+    Completions {
+        #[clap(value_parser)]
+        shell: Shell,
+    },
+    /// Print the settings used, from the config file and the flags, with the defaults filled in
+    Config(ConfigArgs),
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+/// The known findings, to only report the new ones.
+#[derive(clap::Args, Debug)]
+struct BaselineArgs {
+    /// Only report and fail on the findings that are not in this baseline
+    #[clap(long, value_parser, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    baseline: Option<PathBuf>,
 
-    if let Some(generator) = args.gen {
-        print_completions(generator, &mut Args::into_app());
-        return Ok(());
-    };
+    /// Write the current findings to a baseline, to pass to --baseline later
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with = "baseline"
+    )]
+    write_baseline: Option<PathBuf>,
+}
 
-    let dir = args
-        .dir
-        .ok_or_else(|| eyre!("User did not specify directory"))?;
+/// Which files to scan and how to print the report, shared by every subcommand.
+#[derive(clap::Args, Debug)]
+struct ScanArgs {
+    /// Glob of the files to check, relative to the directory. '*' doesn't match '/' but '**'
+    /// matches any number of directories. Can be repeated. Defaults to '**/*.yml' and '**/*.yaml'
+    #[clap(long, value_parser)]
+    include: Vec<String>,
 
-    let paths = paths_to_vec(glob::glob(&format!(
-        "{}/**/*-sops.yml",
-        dir.to_str().unwrap()
-    ))?)?;
+    /// Glob of the files to skip, relative to the directory, matched like --include, e.g.
+    /// 'charts/**' skips every file under 'charts'. Can be repeated
+    #[clap(long, value_parser)]
+    exclude: Vec<String>,
 
-    let keys_used: HashMap<String, HashSet<PathBuf>>;
-    let documents: HashMap<Document, HashSet<PathBuf>>;
-    if args.rotate {
-        (keys_used, documents, _) = try_join3(
-            get_kms_keys(&paths),
-            get_dup_documents(&paths),
-            rotate_kms_keys(&args.kms_arn.expect("A kms arn"), &paths),
-        )
-        .await?;
-    } else {
-        (keys_used, documents) = try_join(get_kms_keys(&paths), get_dup_documents(&paths)).await?;
-    };
+    /// Print absolute paths instead of paths relative to the directory
+    #[clap(long)]
+    absolute_paths: bool,
+
+    /// The format of the report. Defaults to tree
+    #[clap(long, value_parser)]
+    format: Option<Format>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// The directory to check
+    #[clap(value_parser, value_hint = ValueHint::DirPath)]
+    dir: PathBuf,
+
+    #[clap(flatten)]
+    scan: ScanArgs,
+
+    /// Exit with an error when there are findings of this severity or worse. Defaults to error
+    #[clap(long, value_parser)]
+    fail_on: Option<FailOn>,
+
+    #[clap(flatten)]
+    baseline: BaselineArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConfigArgs {
+    /// The directory to find the config file of
+    #[clap(value_parser, value_hint = ValueHint::DirPath, default_value = ".")]
+    dir: PathBuf,
+
+    #[clap(flatten)]
+    scan: ScanArgs,
+
+    /// Exit with an error when there are findings of this severity or worse. Defaults to error
+    #[clap(long, value_parser)]
+    fail_on: Option<FailOn>,
+}
+
+#[derive(clap::Args, Debug)]
+struct KeysArgs {
+    /// The directory to check
+    #[clap(value_parser, value_hint = ValueHint::DirPath)]
+    dir: PathBuf,
+
+    #[clap(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct RotateArgs {
+    /// The directory with the files to rotate
+    #[clap(
+        value_parser,
+        value_hint = ValueHint::DirPath,
+        required_unless_present = "apply-plan",
+        conflicts_with = "apply-plan"
+    )]
+    dir: Option<PathBuf>,
+
+    /// The KMS ARN to rotate to
+    #[clap(
+        long = "kms",
+        value_parser,
+        env = "SOPS_KMS_ARN",
+        required_unless_present = "apply-plan"
+    )]
+    kms_arn: Option<String>,
+
+    #[clap(flatten)]
+    rotation: RotationArgs,
+
+    #[clap(flatten)]
+    scan: ScanArgs,
+}
+
+/// How to rotate, shared by the `rotate` subcommand and the `--rotate` flag.
+#[derive(clap::Args, Debug)]
+struct RotationArgs {
+    /// Only replace this key, the other keys of the files are kept. Can be a glob, e.g.
+    /// 'arn:aws:kms:us-east-1:*'. Can be repeated
+    #[clap(long, value_parser, value_name = "KEY")]
+    from: Vec<String>,
+
+    /// Only replace the keys matching this regex, the other keys of the files are kept. Can be
+    /// repeated
+    #[clap(long, value_parser, value_name = "REGEX")]
+    from_regex: Vec<String>,
+
+    /// Print the files that would be rotated and their keys before and after, without rotating them
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Write the rotation plan to a file instead of rotating, to apply it later with --apply-plan
+    #[clap(long, value_parser, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    write_plan: Option<PathBuf>,
+
+    /// Rotate exactly the files in a plan written by --write-plan
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        conflicts_with_all = &["from", "from-regex", "dry-run", "write-plan"]
+    )]
+    apply_plan: Option<PathBuf>,
+}
+
+impl ScanArgs {
+    /// The settings given on the command line, to override the config file with.
+    fn to_config(&self, fail_on: Option<FailOn>) -> Config {
+        let mut config = Config::default();
+        if !self.include.is_empty() {
+            config.set_include(self.include.clone());
+        }
+        if !self.exclude.is_empty() {
+            config.set_exclude(self.exclude.clone());
+        }
+        config.set_output(OutputConfig::new(
+            self.format.map(value_name),
+            fail_on.map(value_name),
+            self.absolute_paths.then_some(true),
+        ));
+        config
+    }
+}
+
+/// The name of a value on the command line, e.g. `json` for `Format::Json`.
+fn value_name<E: ValueEnum>(value: E) -> String {
+    value
+        .to_possible_value()
+        .expect("No value is skipped")
+        .get_name()
+        .to_string()
+}
+
+/// Parses a value of the config file like it would be on the command line.
+fn parse_value<E: ValueEnum>(value: Option<&str>, name: &str, default: E) -> Result<E> {
+    match value {
+        Some(value) => {
+            E::from_str(value, false).map_err(|e| eyre!("Invalid `{name}` in the config, {e}"))
+        }
+        None => Ok(default),
+    }
+}
+
+/// The settings of a run, the config file of the directory overridden by the command line.
+struct Settings {
+    /// Where the config file was found, if there is one
+    path: Option<PathBuf>,
+    /// Where the directory is from the config file, the globs are relative to the config file
+    glob_prefix: PathBuf,
+    config: Config,
+    format: Format,
+    fail_on: FailOn,
+    absolute_paths: bool,
+}
+
+impl Settings {
+    fn new(dir: &Path, args: &ScanArgs, fail_on: Option<FailOn>) -> Result<Self> {
+        let (path, config) = Config::discover(dir)?;
+        let glob_prefix = Config::glob_prefix(path.as_deref(), dir)?;
+        // The globs on the command line are relative to the directory
+        let config = config.merge(args.to_config(fail_on).with_glob_prefix(&glob_prefix));
+        let output = config.get_output();
+        Ok(Settings {
+            format: parse_value(output.get_format(), "output.format", Format::Tree)?,
+            fail_on: parse_value(output.get_fail_on(), "output.fail-on", FailOn::Error)?,
+            absolute_paths: output.get_absolute_paths().unwrap_or(false),
+            path,
+            glob_prefix,
+            config,
+        })
+    }
+
+    /// The config with the defaults of everything filled in.
+    fn effective(&self) -> Config {
+        let mut config = self.config.effective();
+        config.set_output(OutputConfig::new(
+            Some(value_name(self.format)),
+            Some(value_name(self.fail_on)),
+            Some(self.absolute_paths),
+        ));
+        config
+    }
+}
+
+/// What the trees show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum View {
+    /// Everything, without a subcommand
+    All,
+    Check,
+    Keys,
+    /// Only the rotation
+    Rotation,
+}
+
+/// The formats the report can be printed in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human readable trees
+    Tree,
+    /// The report as JSON, see `libs::flux::Report` for the schema
+    Json,
+    /// The findings as SARIF, for code scanning tools
+    Sarif,
+    /// The findings as a JUnit XML report, for CI test dashboards
+    Junit,
+}
+
+/// The severities to fail on.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FailOn {
+    Error,
+    Warning,
+    Info,
+    /// Never fail because of findings
+    Never,
+}
+
+impl FailOn {
+    fn get_threshold(self) -> Option<Severity> {
+        match self {
+            FailOn::Error => Some(Severity::Error),
+            FailOn::Warning => Some(Severity::Warning),
+            FailOn::Info => Some(Severity::Info),
+            FailOn::Never => None,
+        }
+    }
+}
+
+/// Exit code when there are findings at or above the `--fail-on` severity.
+const EXIT_FINDINGS: u8 = 1;
+/// Exit code when the tool itself failed, same as clap for bad arguments.
+const EXIT_FAILURE: u8 = 2;
+
+/// Set by the completions to run `complete_keys` with the words on the command line instead.
+const COMPLETE_KEYS_ENV: &str = "_FLUX_VALIDATOR_COMPLETE_KEYS";
+
+/// Completes `--kms` and `--from` with `complete_keys` in bash.
+/// The words are split on spaces only, bash would split the ARNs on `:`.
+const BASH_KEYS: &str = r#"
+_flux-validator_keys() {
+    local line="${COMP_LINE:0:COMP_POINT}" words
+    read -ra words <<< "${line}"
+    [[ "${line}" == *" " ]] && words+=("")
+    local cur="${words[-1]}" prev="${words[-2]}"
+    case "${prev}" in
+        --kms|--from)
+            local IFS=$'\n'
+            COMPREPLY=($(compgen -W "$(_FLUX_VALIDATOR_COMPLETE_KEYS=1 "${words[0]}" "${words[@]:1}" 2>/dev/null)" -- "${cur}"))
+            # Bash only replaces what is after the last `:`
+            local colons="${cur%"${cur##*:}"}"
+            COMPREPLY=("${COMPREPLY[@]#"${colons}"}")
+            ;;
+        *)
+            _flux-validator "$@"
+            ;;
+    esac
+}
+
+complete -F _flux-validator_keys -o bashdefault -o default flux-validator
+"#;
+
+/// Completes the `--kms` and `--from` values with `complete_keys` in zsh.
+const ZSH_KEYS: &str = r#"
+_flux-validator_keys() {
+    local -a keys
+    keys=(${(f)"$(_FLUX_VALIDATOR_COMPLETE_KEYS=1 ${words[1]} ${words[2,-1]} 2>/dev/null)"})
+    compadd -a keys
+}
+"#;
+
+/// Completes `--kms` and `--from` with `complete_keys` in fish.
+const FISH_KEYS: &str = r#"
+complete -c flux-validator -l kms -f -a "(env _FLUX_VALIDATOR_COMPLETE_KEYS=1 flux-validator (commandline -opc)[2..-1])"
+complete -c flux-validator -l from -f -a "(env _FLUX_VALIDATOR_COMPLETE_KEYS=1 flux-validator (commandline -opc)[2..-1])"
+"#;
+
+/// Prints the completions, adds the completion of the KMS keys used in the directory for the
+/// shells that can run a command to complete.
+fn print_completions(shell: Shell, cmd: &mut clap::App) -> Result<()> {
+    let mut script = vec![];
+    generate(shell, cmd, cmd.get_name().to_string(), &mut script);
+    let mut script = String::from_utf8(script)?;
+    match shell {
+        Shell::Bash => script.push_str(BASH_KEYS),
+        Shell::Zsh => {
+            // The values of `--kms` and `--from` have no action, they are completed with nothing
+            script = script
+                .replace(":KMS_ARN: '", ":KMS_ARN:_flux-validator_keys'")
+                .replace(":KEY: '", ":KEY:_flux-validator_keys'");
+            // Defined before `_flux-validator` is called at the end
+            let end = script
+                .rfind("_flux-validator \"$@\"")
+                .unwrap_or(script.len());
+            script.insert_str(end, &format!("{}\n", ZSH_KEYS.trim_start()));
+        }
+        Shell::Fish => script.push_str(FISH_KEYS),
+        _ => {}
+    }
+    print!("{script}");
+    Ok(())
+}
+
+/// Prints the KMS keys used in the directory on the command line, for the completions.
+async fn complete_keys(words: &[OsString]) -> Result<()> {
+    // The directory is the first word that is one, the current directory otherwise
+    let dir = words
+        .iter()
+        .map(PathBuf::from)
+        .find(|p| p.is_dir())
+        .unwrap_or_else(|| PathBuf::from("."));
+    let (path, config) = Config::discover(&dir)?;
+    let prefix = Config::glob_prefix(path.as_deref(), &dir)?;
+    let paths = find_files(&dir, &prefix, &config.get_include(), config.get_exclude())?;
+    let scan = scan_documents(&paths).await;
+    for key in get_kms_keys(&scan).await?.keys() {
+        if key.starts_with("arn:") {
+            println!("{key}");
+        }
+    }
+    Ok(())
+}
 
-    // Maybe turn this also into a function
-    let mut key_tree = Tree::new("kms_keys".to_string());
-    for (key, files) in keys_used {
-        let mut key_branch = Tree::new(key);
-        let s_files: HashSet<String> = files
-            .iter()
-            .map(|p| p.to_str().unwrap().to_string())
-            .collect();
-        key_branch.extend(s_files);
+/// Builds a tree of the keys with the files using them as leaves.
+fn build_key_tree(keys: &[KeyUsage]) -> Tree<String> {
+    let mut key_tree = Tree::new("keys".to_string());
+    for k in keys {
+        let mut key_branch = Tree::new(k.get_key().to_string());
+        key_branch.extend(k.get_files().iter().map(|p| p.display().to_string()));
         key_tree.push(key_branch);
     }
+    key_tree
+}
 
-    // This as well?
+/// Builds a tree of the duplicated resources with the files defining them as leaves.
+fn build_dup_tree(duplicates: &[DuplicateGroup]) -> Tree<String> {
     let mut dup_tree = Tree::new("duped documents".to_string());
-    for (doc, path) in documents {
-        if path.len() <= 1 {
-            continue;
+    for group in duplicates {
+        let differs = group.encryption_differs();
+        let mut name_branch = if differs {
+            Tree::new(format!("{} (encrypted differently)", group.get_id()))
+        } else {
+            Tree::new(group.get_id().to_string())
         };
-        let mut name_branch = Tree::new(doc.get_meta().get_name().to_string());
-        let s_files: HashSet<String> = path
-            .iter()
-            .map(|p| p.to_str().unwrap().to_string())
-            .collect();
-        name_branch.extend(s_files);
+        for d in group.get_definitions() {
+            let path = format!("{}:{}", d.get_path().display(), d.get_position());
+            // Only show the key when it is what tells the definitions apart
+            let leaf = match (differs, d.get_keys()) {
+                (false, _) => path.to_string(),
+                (true, []) => format!("{path} (unencrypted)"),
+                (true, keys) => format!("{path} ({})", keys.join(", ")),
+            };
+            name_branch.push(leaf);
+        }
         dup_tree.push(name_branch);
     }
+    dup_tree
+}
 
-    println!("Duped names");
-    println!("{dup_tree}");
-    println!("kms keys used");
-    println!("{key_tree}");
+/// Builds a tree of the skipped files and documents.
+fn build_warning_tree(warnings: &[Warning]) -> Tree<String> {
+    // Documents are scanned file by file, so warnings for the same file are next to each other
+    let mut warning_tree = Tree::new("skipped".to_string());
+    for w in warnings {
+        let path = w.get_path().display().to_string();
+        let leaf = match w.get_position() {
+            Some(position) => format!("{path}:{position}: {w}"),
+            None => w.to_string(),
+        };
+        match warning_tree.leaves.last_mut() {
+            Some(file_branch) if file_branch.root == path => {
+                file_branch.push(leaf);
+            }
+            _ => {
+                warning_tree.push(Tree::new(path).with_leaves([leaf]));
+            }
+        }
+    }
+    warning_tree
+}
 
+/// Builds a tree of the secrets that are not encrypted.
+fn build_unencrypted_tree(findings: &[Finding]) -> Tree<String> {
+    let mut unencrypted_tree = Tree::new("unencrypted secrets".to_string());
+    for f in findings
+        .iter()
+        .filter(|f| f.get_rule() == Rule::UnencryptedSecret)
+    {
+        for l in f.get_locations() {
+            unencrypted_tree.push(format!("{l}: {}", f.get_message()));
+        }
+    }
+    unencrypted_tree
+}
+
+/// Builds a tree of the stages the Flux objects are applied in, and of the ones never applied.
+fn build_apply_order_tree(order: &ApplyOrder) -> Tree<String> {
+    let mut order_tree = Tree::new("apply order".to_string());
+    for (i, stage) in order.get_stages().iter().enumerate() {
+        let mut stage_branch = Tree::new(format!("stage {}", i + 1));
+        stage_branch.extend(stage.iter().map(ToString::to_string));
+        order_tree.push(stage_branch);
+    }
+    if !order.get_blocked().is_empty() {
+        let mut blocked_branch = Tree::new("never applied".to_string());
+        blocked_branch.extend(order.get_blocked().iter().map(ToString::to_string));
+        order_tree.push(blocked_branch);
+    }
+    order_tree
+}
+
+/// Builds a tree of the rotated files, with the keys they moved from and to.
+fn build_rotation_tree(rotation: &RotationReport) -> Tree<String> {
+    if rotation.is_dry_run() {
+        let mut plan_tree = Tree::new(format!("rotate to {}", rotation.get_key()));
+        for f in rotation.get_files() {
+            let path = f.get_path().display();
+            plan_tree.push(format!(
+                "{path}: {} -> {}",
+                f.get_from().join(", "),
+                f.get_to().join(", ")
+            ));
+        }
+        return plan_tree;
+    }
+
+    let mut rotated_branch = Tree::new("rotated".to_string());
+    let mut failed_branch = Tree::new("failed, left unchanged".to_string());
+    for f in rotation.get_files() {
+        let path = f.get_path().display();
+        match f.get_error() {
+            None => rotated_branch.push(format!(
+                "{path}: {} -> {}",
+                f.get_from().join(", "),
+                f.get_to().join(", ")
+            )),
+            Some(e) => failed_branch.push(format!("{path}: {e}")),
+        };
+    }
+    Tree::new("rotation".to_string()).with_leaves([rotated_branch, failed_branch])
+}
+
+/// Prints the parts of the report in the view as trees.
+fn print_tree(report: &Report, view: View) {
+    if matches!(view, View::All | View::Check) {
+        println!("Warnings");
+        println!("{}", build_warning_tree(report.get_warnings()));
+        println!("Duped names");
+        println!("{}", build_dup_tree(report.get_duplicates()));
+        println!("Unencrypted secrets");
+        println!("{}", build_unencrypted_tree(report.get_findings()));
+        println!("Apply order");
+        println!("{}", build_apply_order_tree(report.get_apply_order()));
+    }
+    if matches!(view, View::All | View::Keys) {
+        println!("keys used");
+        println!("{}", build_key_tree(report.get_keys()));
+    }
+    if let Some(rotation) = report.get_rotation() {
+        if rotation.is_dry_run() {
+            println!("Rotation plan");
+            println!("{}", build_rotation_tree(rotation));
+        } else {
+            println!("Rotation");
+            println!("{}", build_rotation_tree(rotation));
+            println!("keys used after rotation");
+            println!(
+                "{}",
+                build_key_tree(rotation.get_keys_after().unwrap_or_default())
+            );
+        }
+    }
+}
+
+/// Prints the report in the format asked for.
+/// Fails after printing if any of the files failed to rotate.
+fn print_report(report: &Report, format: Format, view: View) -> Result<()> {
+    match format {
+        Format::Tree => print_tree(report, view),
+        Format::Json => {
+            serde_json::to_writer_pretty(std::io::stdout(), report)?;
+            println!();
+        }
+        Format::Sarif => {
+            serde_json::to_writer_pretty(std::io::stdout(), &report.to_sarif())?;
+            println!();
+        }
+        Format::Junit => print!("{}", report.to_junit()),
+    }
+
+    if format == Format::Tree && matches!(view, View::All | View::Check) {
+        // Colors only make sense on a terminal
+        let color = match std::io::stdout().is_terminal() {
+            true => ColorChoice::Auto,
+            false => ColorChoice::Never,
+        };
+        report.write_diagnostics(&mut StandardStream::stdout(color))?;
+        let mut summary = format!(
+            "{} errors, {} warnings, {} infos",
+            report.count_findings(Severity::Error),
+            report.count_findings(Severity::Warning),
+            report.count_findings(Severity::Info)
+        );
+        if !report.get_suppressed().is_empty() {
+            summary.push_str(&format!(", {} suppressed", report.get_suppressed().len()));
+        }
+        if !report.get_baselined().is_empty() {
+            summary.push_str(&format!(", {} baselined", report.get_baselined().len()));
+        }
+        println!("{summary}");
+    }
+
+    if let Some(rotation) = report.get_rotation() {
+        let failed = rotation.failed_count();
+        if failed > 0 {
+            return Err(eyre!(
+                "{failed} of {} files failed to rotate",
+                rotation.get_files().len()
+            ));
+        }
+    }
     Ok(())
 }
+
+/// Applies the plan, then reads the rotated files again to see what they are really encrypted
+/// with now.
+async fn rotate(plan: &RotationPlan) -> Result<RotationReport> {
+    let rotations = apply_rotation_plan(plan).await?;
+    let paths: Vec<PathBuf> = rotations.iter().map(|r| r.get_path().clone()).collect();
+    let keys_after = get_kms_keys(&scan_documents(&paths).await).await?;
+    Ok(RotationReport::applied(plan, &rotations, &keys_after))
+}
+
+/// Exits with `EXIT_FINDINGS` if there are findings at or above the threshold.
+fn findings_exit_code(report: &Report, fail_on: FailOn) -> ExitCode {
+    match (report.get_max_severity(), fail_on.get_threshold()) {
+        (Some(worst), Some(threshold)) if worst >= threshold => ExitCode::from(EXIT_FINDINGS),
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// A scanned directory and its report.
+struct Analysis {
+    dir: PathBuf,
+    keys_used: BTreeMap<String, BTreeSet<PathBuf>>,
+    report: Report,
+}
+
+impl Analysis {
+    async fn new(dir: &Path, settings: &Settings) -> Result<Self> {
+        let dir = match settings.absolute_paths {
+            true => dir
+                .canonicalize()
+                .wrap_err_with(|| format!("Could not find {}", dir.display()))?,
+            false => dir.to_path_buf(),
+        };
+        let config = &settings.config;
+        let paths = find_files(
+            &dir,
+            &settings.glob_prefix,
+            &config.get_include(),
+            config.get_exclude(),
+        )?;
+        let scan = scan_documents(&paths).await;
+
+        // Analyse everything before rotating, so nothing is read while sops is rewriting it
+        let (keys_used, documents) =
+            try_join(get_kms_keys(&scan), get_dup_documents(&scan)).await?;
+        let report = Report::with_config(&scan, &keys_used, &documents, config)?;
+        Ok(Analysis {
+            dir,
+            keys_used,
+            report,
+        })
+    }
+
+    /// Plans the rotation, then rotates unless only the plan was asked for.
+    async fn rotate_keys(&mut self, key: &str, args: &RotationArgs) -> Result<()> {
+        let mut from = vec![];
+        for pattern in &args.from {
+            from.push(KeyMatcher::parse_key(pattern)?);
+        }
+        for regex in &args.from_regex {
+            from.push(KeyMatcher::parse_regex(regex)?);
+        }
+        // Most likely a typo, rotating nothing is not what was asked for
+        for m in &from {
+            if !self.keys_used.keys().any(|k| m.matches(k)) {
+                return Err(eyre!("No file uses a key matching {m}"));
+            }
+        }
+
+        let plan = plan_rotation(key, &self.dir, &self.keys_used, &from);
+        if let Some(plan_path) = &args.write_plan {
+            plan.write(plan_path)?;
+            eprintln!("Rotation plan written to {}", plan_path.display());
+        }
+        if args.dry_run || args.write_plan.is_some() {
+            self.report.set_rotation(RotationReport::planned(&plan));
+        } else {
+            self.report.set_rotation(rotate(&plan).await?);
+        }
+        Ok(())
+    }
+
+    /// Writes the findings to a new baseline, or leaves out the ones in the given baseline.
+    /// The findings are all in a new baseline, none is reported.
+    fn apply_baseline(&mut self, args: &BaselineArgs) -> Result<()> {
+        let baseline = match (&args.baseline, &args.write_baseline) {
+            (_, Some(path)) => {
+                let baseline = Baseline::new(&self.report, &self.dir);
+                baseline.write(path)?;
+                eprintln!(
+                    "Baseline of {} findings written to {}",
+                    baseline.count_findings(),
+                    path.display()
+                );
+                baseline
+            }
+            (Some(path), None) => Baseline::read(path)?,
+            (None, None) => return Ok(()),
+        };
+        self.report.apply_baseline(&baseline, &self.dir);
+        Ok(())
+    }
+
+    /// Prints the report, with paths relative to the directory unless asked otherwise.
+    fn print(mut self, settings: &Settings, view: View) -> Result<Report> {
+        if !settings.absolute_paths {
+            self.report.relative_to(&self.dir);
+        }
+        print_report(&self.report, settings.format, view)?;
+        Ok(self.report)
+    }
+}
+
+/// Rotates the files in the plan written by `--write-plan`, without scanning anything.
+async fn apply_plan(plan_path: &Path, format: Format) -> Result<()> {
+    let plan = RotationPlan::read(plan_path)?;
+    let mut report = Report::from_rotation(rotate(&plan).await?);
+    report.relative_to(plan.get_root());
+    print_report(&report, format, View::Rotation)
+}
+
+/// Without a subcommand, checks, lists the keys and rotates if asked to, all at once.
+async fn run_flags(args: Args) -> Result<ExitCode> {
+    if let Some(generator) = args.gen {
+        print_completions(generator, &mut Args::into_app())?;
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    if let Some(plan_path) = &args.rotation.apply_plan {
+        apply_plan(plan_path, args.scan.format.unwrap_or(Format::Tree)).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let dir = args
+        .dir
+        .ok_or_else(|| eyre!("User did not specify directory"))?;
+    let settings = Settings::new(&dir, &args.scan, args.fail_on)?;
+    let mut analysis = Analysis::new(&dir, &settings).await?;
+    analysis.apply_baseline(&args.baseline)?;
+    if args.rotate {
+        analysis
+            .rotate_keys(args.kms_arn.as_deref().expect("A kms arn"), &args.rotation)
+            .await?;
+    }
+    let report = analysis.print(&settings, View::All)?;
+    Ok(findings_exit_code(&report, settings.fail_on))
+}
+
+async fn run_rotate(args: RotateArgs) -> Result<ExitCode> {
+    if let Some(plan_path) = &args.rotation.apply_plan {
+        apply_plan(plan_path, args.scan.format.unwrap_or(Format::Tree)).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let dir = args.dir.expect("A directory");
+    let settings = Settings::new(&dir, &args.scan, None)?;
+    let mut analysis = Analysis::new(&dir, &settings).await?;
+    analysis
+        .rotate_keys(args.kms_arn.as_deref().expect("A kms arn"), &args.rotation)
+        .await?;
+    analysis.print(&settings, View::Rotation)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run(mut args: Args) -> Result<ExitCode> {
+    match args.command.take() {
+        Some(Command::Check(check)) => {
+            let settings = Settings::new(&check.dir, &check.scan, check.fail_on)?;
+            let mut analysis = Analysis::new(&check.dir, &settings).await?;
+            analysis.apply_baseline(&check.baseline)?;
+            let report = analysis.print(&settings, View::Check)?;
+            Ok(findings_exit_code(&report, settings.fail_on))
+        }
+        Some(Command::Keys(keys)) => {
+            let settings = Settings::new(&keys.dir, &keys.scan, None)?;
+            let analysis = Analysis::new(&keys.dir, &settings).await?;
+            analysis.print(&settings, View::Keys)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Rotate(rotate)) => run_rotate(rotate).await,
+        Some(Command::Completions { shell }) => {
+            print_completions(shell, &mut Args::into_app())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Config(config)) => {
+            let settings = Settings::new(&config.dir, &config.scan, config.fail_on)?;
+            match &settings.path {
+                Some(path) => println!("# {}", path.display()),
+                None => {
+                    println!("# No {CONFIG_FILE} found, only the flags and the defaults are used")
+                }
+            }
+            print!("{}", settings.effective().to_toml()?);
+            Ok(ExitCode::SUCCESS)
+        }
+        None => run_flags(args).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let result = match std::env::var_os(COMPLETE_KEYS_ENV) {
+        Some(_) => {
+            let words: Vec<OsString> = std::env::args_os().skip(1).collect();
+            complete_keys(&words).await.map(|()| ExitCode::SUCCESS)
+        }
+        None => run(Args::parse()).await,
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -1,22 +1,61 @@
-use eyre::Result;
-use serde::Deserialize;
-use serde_yaml::Deserializer;
+use eyre::{eyre, Result, WrapErr};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Deserializer, Value};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
     fs::File,
-    path::PathBuf,
-    process::Command,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
 };
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+use walkdir::WalkDir;
+use yaml_rust::{scanner::Marker, YamlLoader};
+
+mod baseline;
+mod config;
+mod crd;
+mod dependency;
+mod diagnostic;
+mod junit;
+mod report;
+mod sarif;
+mod source;
+mod suppression;
+pub use baseline::*;
+pub use config::*;
+pub use crd::*;
+pub use dependency::*;
+pub use report::*;
+use source::{document_sources, DocumentSource};
+use suppression::find_suppressions;
+pub use suppression::Suppression;
 
 type Paths = Vec<PathBuf>;
 
+/// Serializes a path even when it is not valid UTF-8, the invalid parts are replaced.
+fn serialize_path<S: serde::Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_paths<S: serde::Serializer>(
+    paths: &[PathBuf],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|p| p.to_string_lossy()))
+}
+
 /// A struct representing a k8s document.
-/// Stores the kind, name, namespace, and sops information.
-/// Should be equal when the kind, metadata information, and sops data is the same.
-/// There is a potential bug if two items have the same name but encrypted differently.
-/// Should manually implement Eq and Hash in that case.
+/// Stores the api version, kind, name, namespace, and sops information.
+/// Use `Document::get_id` to compare documents, the sops data is not part of the identity.
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
 pub struct Document {
+    /// The api version of the document, e.g. "apps/v1" or "v1"
+    #[serde(rename = "apiVersion", default)]
+    api_version: String,
     /// The kind of the document, usually a "deployment"
     kind: String,
     /// Metadata, name and namespace
@@ -34,14 +73,146 @@ pub struct Metadata {
     namespace: Option<String>,
 }
 
+/// Identifies a k8s resource, two documents with the same id would conflict when applied.
+/// Made of the api group, kind, namespace and name. The api version is ignored since the
+/// same resource can be served under several versions.
+/// Sorts by api group, kind, namespace then name.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Serialize)]
+pub struct ResourceId {
+    group: String,
+    kind: String,
+    namespace: Option<String>,
+    name: String,
+}
+
+/// A single definition of a resource, the file it is in and how it is encrypted.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Definition {
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0
+    index: usize,
+    /// Where the `metadata.name` of the definition is
+    position: Position,
+    sops: Option<Sops>,
+}
+
 /// SOPS helper struct
-#[derive(Debug, serde_query::Deserialize, Eq, PartialEq, Hash, Clone)]
+/// Only keeps who the file is encrypted for, the encrypted data keys and the rest of the sops
+/// metadata are ignored.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone, Default)]
 pub struct Sops {
-    #[query(".kms.[0].arn")]
+    /// The top level recipients, any one of them can decrypt the file
+    #[serde(flatten)]
+    recipients: Recipients,
+    /// Shamir key groups, a key from several groups is needed to decrypt the file
+    #[serde(default, deserialize_with = "null_as_empty")]
+    key_groups: Vec<Recipients>,
+    /// Which values are encrypted, at most one of these is set
+    encrypted_regex: Option<String>,
+    unencrypted_regex: Option<String>,
+    encrypted_suffix: Option<String>,
+    unencrypted_suffix: Option<String>,
+}
+
+/// Only used to read the sops block of a file, without caring about the rest of it.
+#[derive(Deserialize)]
+struct SopsBlock {
+    sops: Option<Sops>,
+}
+
+/// The recipients of a sops file, grouped by type.
+/// sops writes the unused types as either an empty list or `null`.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone, Default)]
+pub struct Recipients {
+    #[serde(default, deserialize_with = "null_as_empty")]
+    kms: Vec<KmsKey>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pgp: Vec<PgpKey>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    age: Vec<AgeKey>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    gcp_kms: Vec<GcpKmsKey>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    azure_kv: Vec<AzureKvKey>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    hc_vault: Vec<HcVaultKey>,
+}
+
+/// An AWS KMS key, optionally assumed through a role and with an encryption context.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct KmsKey {
     arn: String,
+    role: Option<String>,
+    #[serde(default)]
+    context: BTreeMap<String, String>,
+    aws_profile: Option<String>,
+}
+
+/// A PGP key, identified by its fingerprint.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct PgpKey {
+    fp: String,
+}
+
+/// An age recipient, i.e. a public key.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct AgeKey {
+    recipient: String,
+}
+
+/// A GCP KMS key.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct GcpKmsKey {
+    resource_id: String,
+}
+
+/// An Azure Key Vault key.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct AzureKvKey {
+    vault_url: String,
+    name: String,
+    version: String,
+}
+
+/// A HashiCorp Vault transit key.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct HcVaultKey {
+    vault_address: String,
+    engine_path: String,
+    key_name: String,
+}
+
+fn null_as_empty<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl Document {
+    pub fn get_id(&self) -> ResourceId {
+        // The core group has no prefix, e.g. "v1"
+        let group = match self.api_version.rsplit_once('/') {
+            Some((group, _)) => group.to_string(),
+            None => String::new(),
+        };
+        ResourceId {
+            group,
+            kind: self.kind.clone(),
+            namespace: self.meta.namespace.clone(),
+            name: self.meta.name.clone(),
+        }
+    }
+
+    pub fn get_api_version(&self) -> &str {
+        &self.api_version
+    }
+
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
     pub fn get_meta(&self) -> &Metadata {
         &self.meta
     }
@@ -56,15 +227,225 @@ impl Document {
 }
 
 impl Sops {
+    pub fn get_recipients(&self) -> &Recipients {
+        &self.recipients
+    }
+
+    pub fn get_key_groups(&self) -> &[Recipients] {
+        &self.key_groups
+    }
+
+    /// The ids of every key the file is encrypted for, including the ones in key groups.
+    /// Keys are in the order sops lists them, without duplicates.
+    pub fn get_key_ids(&self) -> Vec<String> {
+        let mut ids = self.recipients.get_key_ids();
+        for id in self.key_groups.iter().flat_map(Recipients::get_key_ids) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// The type of a key of the file, as in the `sops --rm-<type>` flags, e.g. `kms` or `age`.
+    fn get_key_type(&self, id: &str) -> Option<&'static str> {
+        std::iter::once(&self.recipients)
+            .chain(&self.key_groups)
+            .flat_map(Recipients::get_typed_key_ids)
+            .find(|(_, key)| key == id)
+            .map(|(key_type, _)| key_type)
+    }
+}
+
+impl Recipients {
+    pub fn get_kms(&self) -> &[KmsKey] {
+        &self.kms
+    }
+
+    pub fn get_pgp(&self) -> &[PgpKey] {
+        &self.pgp
+    }
+
+    pub fn get_age(&self) -> &[AgeKey] {
+        &self.age
+    }
+
+    pub fn get_gcp_kms(&self) -> &[GcpKmsKey] {
+        &self.gcp_kms
+    }
+
+    pub fn get_azure_kv(&self) -> &[AzureKvKey] {
+        &self.azure_kv
+    }
+
+    pub fn get_hc_vault(&self) -> &[HcVaultKey] {
+        &self.hc_vault
+    }
+
+    /// The ids of the keys, see the `get_id` of each key type.
+    pub fn get_key_ids(&self) -> Vec<String> {
+        self.get_typed_key_ids()
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    /// The ids of the keys along with their type, as in the `sops --rm-<type>` flags.
+    fn get_typed_key_ids(&self) -> Vec<(&'static str, String)> {
+        let typed = |key_type, ids: Vec<String>| ids.into_iter().map(move |id| (key_type, id));
+        typed("kms", self.kms.iter().map(KmsKey::get_id).collect())
+            .chain(typed(
+                "pgp",
+                self.pgp.iter().map(|k| k.fp.clone()).collect(),
+            ))
+            .chain(typed(
+                "age",
+                self.age.iter().map(|k| k.recipient.clone()).collect(),
+            ))
+            .chain(typed(
+                "gcp-kms",
+                self.gcp_kms.iter().map(|k| k.resource_id.clone()).collect(),
+            ))
+            .chain(typed(
+                "azure-kv",
+                self.azure_kv.iter().map(AzureKvKey::get_id).collect(),
+            ))
+            .chain(typed(
+                "hc-vault-transit",
+                self.hc_vault.iter().map(HcVaultKey::get_id).collect(),
+            ))
+            .collect()
+    }
+}
+
+impl KmsKey {
     pub fn get_arn(&self) -> &str {
         &self.arn
     }
+
+    pub fn get_role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+
+    pub fn get_context(&self) -> &BTreeMap<String, String> {
+        &self.context
+    }
+
+    pub fn get_aws_profile(&self) -> Option<&str> {
+        self.aws_profile.as_deref()
+    }
+
+    /// The key as passed to `sops --kms`, `arn` or `arn+role`.
+    pub fn get_id(&self) -> String {
+        match &self.role {
+            Some(role) => format!("{}+{role}", self.arn),
+            None => self.arn.clone(),
+        }
+    }
+}
+
+impl PgpKey {
+    pub fn get_fingerprint(&self) -> &str {
+        &self.fp
+    }
+}
+
+impl AgeKey {
+    pub fn get_recipient(&self) -> &str {
+        &self.recipient
+    }
+}
+
+impl GcpKmsKey {
+    pub fn get_resource_id(&self) -> &str {
+        &self.resource_id
+    }
+}
+
+impl AzureKvKey {
+    /// The key as passed to `sops --azure-kv`, `{vault_url}/keys/{name}/{version}`.
+    pub fn get_id(&self) -> String {
+        format!("{}/keys/{}/{}", self.vault_url, self.name, self.version)
+    }
+}
+
+impl HcVaultKey {
+    /// The key as passed to `sops --hc-vault-transit`,
+    /// `{vault_address}/v1/{engine_path}/keys/{key_name}`.
+    pub fn get_id(&self) -> String {
+        format!(
+            "{}/v1/{}/keys/{}",
+            self.vault_address, self.engine_path, self.key_name
+        )
+    }
 }
 
 impl Metadata {
     pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+}
+
+impl ResourceId {
+    pub fn get_group(&self) -> &str {
+        &self.group
+    }
+
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for ResourceId {
+    /// Formats like `kubectl`, e.g. `Deployment.apps default/podinfo`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if !self.group.is_empty() {
+            write!(f, ".{}", self.group)?;
+        }
+        match &self.namespace {
+            Some(ns) => write!(f, " {}/{}", ns, self.name),
+            None => write!(f, " {}", self.name),
+        }
+    }
+}
+
+impl Definition {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_position(&self) -> Position {
+        self.position
+    }
+
+    pub fn get_sops(&self) -> &Option<Sops> {
+        &self.sops
+    }
+}
+
+/// Checks if the definitions of a resource are not all encrypted the same way.
+pub fn encryption_differs(definitions: &[Definition]) -> bool {
+    match definitions.split_first() {
+        Some((first, rest)) => rest.iter().any(|d| d.sops != first.sops),
+        None => false,
+    }
 }
 
 pub fn paths_to_vec(paths: glob::Paths) -> Result<Vec<PathBuf>> {
@@ -75,60 +456,1031 @@ pub fn paths_to_vec(paths: glob::Paths) -> Result<Vec<PathBuf>> {
     Ok(v)
 }
 
-pub async fn get_kms_keys(paths: &Paths) -> Result<HashMap<String, HashSet<PathBuf>>> {
-    let mut keys_used = HashMap::<String, HashSet<PathBuf>>::new();
+/// Finds the files under `dir` matching any of the `include` globs and none of the `exclude` globs.
+/// The globs are relative to the directory `dir` is at `prefix` in, e.g. `apps/**/*.yml` for
+/// `apps`, see `Config::glob_prefix`. The prefix is empty when they are relative to `dir`.
+/// The files are sorted.
+pub fn find_files(
+    dir: &Path,
+    prefix: &Path,
+    include: &[String],
+    exclude: &[String],
+) -> Result<Paths> {
+    let patterns = |globs: &[String]| {
+        globs
+            .iter()
+            .map(|g| glob::Pattern::new(g))
+            .collect::<std::result::Result<Vec<_>, _>>()
+    };
+    let include = patterns(include)?;
+    let exclude = patterns(exclude)?;
+    // Like a shell, `*` doesn't match `/` but `**` does, e.g. `charts/**` skips every chart
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    let mut v = vec![];
+    for entry in WalkDir::new(dir).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        // Globs are strings, names that are not UTF-8 are only matched lossily, the path is kept
+        let relative = prefix.join(entry.path().strip_prefix(dir).unwrap_or(entry.path()));
+        let relative = relative.to_string_lossy();
+        if include.iter().any(|p| p.matches_with(&relative, options))
+            && !exclude.iter().any(|e| e.matches_with(&relative, options))
+        {
+            v.push(entry.into_path());
+        }
+    }
+    v.sort();
+    Ok(v)
+}
+
+/// A position in a file, both the line and column start from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+impl From<&Marker> for Position {
+    /// yaml-rust lines start from 1 but columns from 0
+    fn from(m: &Marker) -> Self {
+        Position {
+            line: m.line(),
+            column: m.col() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A k8s document found while scanning, along with where it was found.
+#[derive(Debug, Clone)]
+pub struct ScannedDocument {
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0
+    index: usize,
+    source: DocumentSource,
+    document: Document,
+    /// Absent when the document is not a Flux object
+    flux: Option<FluxObject>,
+}
+
+/// Something that was skipped while scanning.
+/// Skipped documents are not validated, but they don't stop the rest of the repo from being validated.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Warning {
+    /// An empty document, e.g. a trailing `---`
+    Empty {
+        #[serde(serialize_with = "serialize_path")]
+        path: PathBuf,
+        index: usize,
+        position: Position,
+    },
+    /// Valid YAML that is not a k8s object, e.g. a `kustomization.yaml` or helm values
+    NotKubernetes {
+        #[serde(serialize_with = "serialize_path")]
+        path: PathBuf,
+        index: usize,
+        position: Position,
+        reason: String,
+    },
+    /// Looks like a k8s object but could not be read
+    Invalid {
+        #[serde(serialize_with = "serialize_path")]
+        path: PathBuf,
+        index: usize,
+        position: Position,
+        error: String,
+    },
+    /// A Flux object whose spec could not be read, it is still validated but not as a Flux object
+    InvalidFlux {
+        #[serde(serialize_with = "serialize_path")]
+        path: PathBuf,
+        index: usize,
+        position: Position,
+        error: String,
+    },
+    /// The file could not be read or is not valid YAML, the whole file is skipped.
+    /// serde_yaml reads every document before returning any, so the valid ones are lost too
+    ParseError {
+        #[serde(serialize_with = "serialize_path")]
+        path: PathBuf,
+        error: String,
+        /// Where the YAML stops being valid
+        position: Option<Position>,
+    },
+}
+
+/// The result of scanning files, the k8s documents found and what was skipped.
+#[derive(Debug, Default, Clone)]
+pub struct Scan {
+    /// Every file scanned, even the ones that could not be parsed
+    files: Paths,
+    documents: Vec<ScannedDocument>,
+    warnings: Vec<Warning>,
+    /// The ignore comments of the files
+    suppressions: Vec<Suppression>,
+}
+
+impl ScannedDocument {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    /// Where the document starts.
+    pub fn get_position(&self) -> Position {
+        self.source.position
+    }
+
+    /// Where a field of the document is, by path, e.g. `metadata.name` or `spec.dependsOn[0].name`.
+    pub fn get_field_position(&self, field: &str) -> Option<Position> {
+        self.source.fields.get(field).copied()
+    }
+
+    pub fn get_document(&self) -> &Document {
+        &self.document
+    }
+
+    /// The Flux model of the document, if it is a Flux object.
+    pub fn get_flux(&self) -> Option<&FluxObject> {
+        self.flux.as_ref()
+    }
+}
+
+impl Warning {
+    pub fn get_path(&self) -> &PathBuf {
+        match self {
+            Warning::Empty { path, .. }
+            | Warning::NotKubernetes { path, .. }
+            | Warning::Invalid { path, .. }
+            | Warning::InvalidFlux { path, .. }
+            | Warning::ParseError { path, .. } => path,
+        }
+    }
+
+    fn get_path_mut(&mut self) -> &mut PathBuf {
+        match self {
+            Warning::Empty { path, .. }
+            | Warning::NotKubernetes { path, .. }
+            | Warning::Invalid { path, .. }
+            | Warning::InvalidFlux { path, .. }
+            | Warning::ParseError { path, .. } => path,
+        }
+    }
+
+    /// Where the skipped document starts, or where the YAML stops being valid.
+    pub fn get_position(&self) -> Option<Position> {
+        match self {
+            Warning::Empty { position, .. }
+            | Warning::NotKubernetes { position, .. }
+            | Warning::Invalid { position, .. }
+            | Warning::InvalidFlux { position, .. } => Some(*position),
+            Warning::ParseError { position, .. } => *position,
+        }
+    }
+}
+
+/// The number of the document at `index` in its file, documents are numbered from 1 for humans.
+fn document_number(index: usize) -> usize {
+    index + 1
+}
+
+impl fmt::Display for Warning {
+    /// Describes the warning, without the path.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Empty { index, .. } => {
+                write!(f, "document {} is empty", document_number(*index))
+            }
+            Warning::NotKubernetes { index, reason, .. } => {
+                let number = document_number(*index);
+                write!(f, "document {number} is not a k8s object, {reason}")
+            }
+            Warning::Invalid { index, error, .. } => {
+                write!(
+                    f,
+                    "document {} is invalid, {error}",
+                    document_number(*index)
+                )
+            }
+            Warning::InvalidFlux { index, error, .. } => {
+                let number = document_number(*index);
+                write!(f, "document {number} is not a valid Flux object, {error}")
+            }
+            Warning::ParseError { error, .. } => write!(f, "could not parse file, {error}"),
+        }
+    }
+}
+
+impl Scan {
+    pub fn get_files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn get_documents(&self) -> &[ScannedDocument] {
+        &self.documents
+    }
+
+    pub fn get_warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn get_suppressions(&self) -> &[Suppression] {
+        &self.suppressions
+    }
+}
+
+/// Finds why a YAML document is not a k8s object, if it is not one.
+/// Anything with an `apiVersion`, a `kind` and a `metadata.name` is considered a k8s object.
+fn not_kubernetes_reason(value: &Value) -> Option<String> {
+    let map = match value {
+        Value::Mapping(map) => map,
+        _ => return Some("not a mapping".to_string()),
+    };
+    let field = |name: &str| map.get(&Value::String(name.to_string()));
+    for name in ["apiVersion", "kind"] {
+        if !matches!(field(name), Some(Value::String(_))) {
+            return Some(format!("missing `{name}`"));
+        }
+    }
+    match field("metadata").and_then(|m| m.get("name")) {
+        Some(_) => None,
+        None => Some("missing `metadata.name`".to_string()),
+    }
+}
+
+/// Finds where the YAML stops being valid.
+/// serde_yaml doesn't give the location of syntax errors, yaml-rust does.
+fn error_position(content: &str) -> Option<Position> {
+    YamlLoader::load_from_str(content)
+        .err()
+        .map(|e| Position::from(e.marker()))
+}
+
+/// Reads all the documents in the paths.
+/// Documents that are not k8s objects and files that can't be parsed are skipped with a warning.
+pub async fn scan_documents(paths: &Paths) -> Scan {
+    let mut scan = Scan {
+        files: paths.clone(),
+        ..Scan::default()
+    };
     for path in paths {
-        let f = File::open(path.clone())?;
-        for s in Deserializer::from_reader(f) {
-            let d = Document::deserialize(s)?;
-            if let Some(sops) = d.sops {
-                if let Some(docs) = keys_used.get_mut(sops.get_arn()) {
-                    // Key already found, add to the set of files using it
-                    docs.insert(path.clone());
-                } else {
-                    // Key not used before, create a new set and add it.
-                    let mut docs = HashSet::<PathBuf>::new();
-                    docs.insert(path.clone());
-                    keys_used.insert(sops.get_arn().to_string(), docs);
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                scan.warnings.push(Warning::ParseError {
+                    path: path.clone(),
+                    error: e.to_string(),
+                    position: None,
+                });
+                continue;
+            }
+        };
+        let sources = document_sources(&content);
+        scan.suppressions
+            .extend(find_suppressions(path, &content, &sources));
+        let mut sources = sources.into_iter();
+        for (index, s) in Deserializer::from_str(&content).enumerate() {
+            let value = match Value::deserialize(s) {
+                Ok(v) => v,
+                Err(e) => {
+                    // A syntax error anywhere fails the first document already, and the
+                    // deserializer keeps returning it, nothing of the file can be read
+                    scan.warnings.push(Warning::ParseError {
+                        path: path.clone(),
+                        error: e.to_string(),
+                        position: error_position(&content),
+                    });
+                    break;
+                }
+            };
+            let path = path.clone();
+            let source = sources.next().unwrap_or_default();
+            let position = source.position;
+            if value.is_null() {
+                scan.warnings.push(Warning::Empty {
+                    path,
+                    index,
+                    position,
+                });
+            } else if let Some(reason) = not_kubernetes_reason(&value) {
+                scan.warnings.push(Warning::NotKubernetes {
+                    path,
+                    index,
+                    position,
+                    reason,
+                });
+            } else {
+                // The values of an encrypted document are not what they are once decrypted,
+                // e.g. `prune: ENC[...]`, it is only read as a k8s object
+                let flux = match value.get("sops") {
+                    Some(_) => Ok(None),
+                    None => FluxObject::from_value(&value),
                 };
+                match serde_yaml::from_value(value) {
+                    Ok(document) => {
+                        let flux = flux.unwrap_or_else(|e| {
+                            scan.warnings.push(Warning::InvalidFlux {
+                                path: path.clone(),
+                                index,
+                                position,
+                                error: format!("{e:#}"),
+                            });
+                            None
+                        });
+                        scan.documents.push(ScannedDocument {
+                            path,
+                            index,
+                            source,
+                            document,
+                            flux,
+                        })
+                    }
+                    Err(e) => scan.warnings.push(Warning::Invalid {
+                        path,
+                        index,
+                        position,
+                        error: e.to_string(),
+                    }),
+                }
             }
         }
     }
+    scan
+}
+
+/// Maps every key used by sops to the files encrypted with it.
+pub async fn get_kms_keys(scan: &Scan) -> Result<BTreeMap<String, BTreeSet<PathBuf>>> {
+    let mut keys_used = BTreeMap::<String, BTreeSet<PathBuf>>::new();
+    for d in &scan.documents {
+        let sops = match d.document.get_sops() {
+            Some(sops) => sops,
+            None => continue,
+        };
+        // A file is indexed under every key that can decrypt it
+        for key in sops.get_key_ids() {
+            keys_used.entry(key).or_default().insert(d.path.clone());
+        }
+    }
     Ok(keys_used)
 }
 
-pub async fn rotate_kms_keys(key: &str, paths: &Paths) -> Result<()> {
-    for path in paths {
-        Command::new("sops")
-            .args(["-d", "-i", path.to_str().unwrap()])
-            .output()?;
+/// Inverts the result of `get_kms_keys`, maps every encrypted file to the keys it uses.
+/// The files and the keys of a file are sorted.
+pub fn get_keys_by_file(
+    keys_used: &BTreeMap<String, BTreeSet<PathBuf>>,
+) -> BTreeMap<PathBuf, Vec<String>> {
+    let mut keys_by_file = BTreeMap::<PathBuf, Vec<String>>::new();
+    for (key, files) in keys_used {
+        for f in files {
+            keys_by_file.entry(f.clone()).or_default().push(key.clone());
+        }
+    }
+    // The keys are visited in order, so the keys of a file are already sorted
+    keys_by_file
+}
+
+/// The files to re-encrypt with a new key, along with the keys they are encrypted with now.
+/// Can be written to a file to be reviewed, then applied as is later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationPlan {
+    /// The kms key to encrypt the files with
+    key: String,
+    /// The directory that was scanned. Written relative to the plan file, and the paths of the
+    /// files relative to it, so the plan can be applied from anywhere
+    root: PathBuf,
+    files: Vec<PlannedRotation>,
+}
+
+/// A file to rotate in a `RotationPlan`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedRotation {
+    path: PathBuf,
+    /// The keys the file was encrypted with when the plan was made, sorted
+    from: Vec<String>,
+    /// The keys of `from` replaced by the new key, the others are kept
+    replace: Vec<String>,
+}
 
-        // Encrypt the file
-        Command::new("sops")
-            .args(["-e", "-i", "-k", key, path.to_str().unwrap()])
-            .output()?;
+impl RotationPlan {
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn get_files(&self) -> &[PlannedRotation] {
+        &self.files
+    }
+
+    /// Reads a plan written by `RotationPlan::write`, the paths are found from the plan file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let f = File::open(path).wrap_err_with(|| format!("could not open {}", path.display()))?;
+        let mut plan: RotationPlan = serde_yaml::from_reader(f)
+            .wrap_err_with(|| format!("invalid plan {}", path.display()))?;
+        plan.root = parent_dir(path).join(&plan.root);
+        for f in &mut plan.files {
+            f.path = plan.root.join(&f.path);
+        }
+        Ok(plan)
+    }
+
+    /// Writes the plan as YAML, with the directory relative to the plan file.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let canonicalize = |dir: &Path| {
+            dir.canonicalize()
+                .wrap_err_with(|| format!("Could not find {}", dir.display()))
+        };
+        let mut plan = self.clone();
+        plan.root = relative_path(&canonicalize(parent_dir(path))?, &canonicalize(&self.root)?);
+        for f in &mut plan.files {
+            f.path = f
+                .path
+                .strip_prefix(&self.root)
+                .unwrap_or(&f.path)
+                .to_path_buf();
+        }
+        let f =
+            File::create(path).wrap_err_with(|| format!("could not create {}", path.display()))?;
+        Ok(serde_yaml::to_writer(f, &plan)?)
     }
-    Ok(())
 }
 
-pub async fn get_dup_documents(paths: &Paths) -> Result<HashMap<Document, HashSet<PathBuf>>> {
-    let mut documents = HashMap::<Document, HashSet<PathBuf>>::new();
-    for path in paths {
-        let f = File::open(path.clone())?;
-        for s in Deserializer::from_reader(f) {
-            let d = Document::deserialize(s)?;
-            if let Some(docs) = documents.get_mut(&d) {
-                // Document already found, add path to the set
-                // Probably means the document is duped
-                docs.insert(path.clone());
-            } else {
-                // Document not found before, create a new set and add path
-                let mut docs = HashSet::<PathBuf>::new();
-                docs.insert(path.clone());
-                documents.insert(d, docs);
-            };
+/// The directory of a file, `.` when the path is only a file name.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// The path to `to` from the directory `from`, both absolute, e.g. `../apps` to `/repo/apps`
+/// from `/repo/plans`.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let common = from
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path: PathBuf = from
+        .components()
+        .skip(common)
+        .map(|_| std::path::Component::ParentDir)
+        .collect();
+    path.extend(to.components().skip(common));
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+    path
+}
+
+impl PlannedRotation {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_from(&self) -> &[String] {
+        &self.from
+    }
+
+    pub fn get_replace(&self) -> &[String] {
+        &self.replace
+    }
+
+    /// The keys the file is encrypted with once rotated to `key`, sorted.
+    pub fn get_to(&self, key: &str) -> Vec<String> {
+        let mut to: Vec<String> = self
+            .from
+            .iter()
+            .filter(|k| !self.replace.contains(k))
+            .cloned()
+            .chain(std::iter::once(key.to_string()))
+            .collect();
+        to.sort();
+        to.dedup();
+        to
+    }
+}
+
+/// Selects keys by their id, e.g. to only rotate the files using a retired key.
+#[derive(Debug, Clone)]
+pub enum KeyMatcher {
+    Exact(String),
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl KeyMatcher {
+    /// Parses a key id, or a glob if it has any of `*?[`. Key ids never have those.
+    pub fn parse_key(key: &str) -> Result<Self> {
+        if key.contains(['*', '?', '[']) {
+            Ok(KeyMatcher::Glob(glob::Pattern::new(key)?))
+        } else {
+            Ok(KeyMatcher::Exact(key.to_string()))
+        }
+    }
+
+    pub fn parse_regex(regex: &str) -> Result<Self> {
+        Ok(KeyMatcher::Regex(Regex::new(regex)?))
+    }
+
+    /// Whether the key id is matched. A kms key assumed through a role, `arn+role`, is also
+    /// matched by its arn alone, whatever the role is.
+    pub fn matches(&self, key: &str) -> bool {
+        let matches = |key: &str| match self {
+            KeyMatcher::Exact(k) => k == key,
+            KeyMatcher::Glob(p) => p.matches(key),
+            KeyMatcher::Regex(r) => r.is_match(key),
+        };
+        matches(key) || kms_arn(key).is_some_and(matches)
+    }
+}
+
+/// The arn of a kms key assumed through a role, from its `arn+role` id, see `KmsKey::get_id`.
+fn kms_arn(key: &str) -> Option<&str> {
+    let (arn, _role) = key.split_once('+')?;
+    arn.starts_with("arn:").then_some(arn)
+}
+
+impl fmt::Display for KeyMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyMatcher::Exact(k) => write!(f, "{k}"),
+            KeyMatcher::Glob(p) => write!(f, "{p}"),
+            KeyMatcher::Regex(r) => write!(f, "/{r}/"),
+        }
+    }
+}
+
+/// Plans the rotation of the encrypted files under `root` to the kms `key`, using the result of
+/// `get_kms_keys`.
+/// When `from` is not empty, only the keys matched by one of them are replaced by `key` and the
+/// other recipients of the files are kept, otherwise every key is replaced.
+/// Files with nothing to replace are left out. The files are sorted by path.
+pub fn plan_rotation(
+    key: &str,
+    root: &Path,
+    keys_used: &BTreeMap<String, BTreeSet<PathBuf>>,
+    from: &[KeyMatcher],
+) -> RotationPlan {
+    let replaced = |k: &String| k != key && (from.is_empty() || from.iter().any(|m| m.matches(k)));
+    let files: Vec<PlannedRotation> = get_keys_by_file(keys_used)
+        .into_iter()
+        .map(|(path, from)| PlannedRotation {
+            replace: from.iter().filter(|k| replaced(k)).cloned().collect(),
+            path,
+            from,
+        })
+        .filter(|planned| !planned.replace.is_empty())
+        .collect();
+    RotationPlan {
+        key: key.to_string(),
+        root: root.to_path_buf(),
+        files,
+    }
+}
+
+/// The outcome of rotating the key of a single file.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    path: PathBuf,
+    status: RotationStatus,
+}
+
+/// Whether a file was rotated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RotationStatus {
+    Rotated,
+    /// The file was left unchanged, with why the rotation failed
+    Failed(String),
+}
+
+impl Rotation {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_status(&self) -> &RotationStatus {
+        &self.status
+    }
+
+    pub fn is_rotated(&self) -> bool {
+        self.status == RotationStatus::Rotated
+    }
+}
+
+/// Reads the first sops block in the file, if any.
+fn read_sops(path: &Path) -> Result<Option<Sops>> {
+    let f = File::open(path)?;
+    for s in Deserializer::from_reader(f) {
+        if let Some(sops) = SopsBlock::deserialize(s)?.sops {
+            return Ok(Some(sops));
         }
     }
+    Ok(None)
+}
+
+/// Runs sops and returns its stdout, the output stays in memory.
+/// Fails with what sops printed to stderr if it exits with an error.
+async fn run_sops(cmd: &mut Command) -> Result<Vec<u8>> {
+    let output = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(eyre!("sops failed ({}): {}", output.status, stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Atomically replaces the file with `contents`, keeping its permissions.
+/// Goes through a temporary file in the same directory, a rename can't cross file systems.
+fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = parent_dir(path);
+    let permissions = std::fs::metadata(path)?.permissions();
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(contents)?;
+    tmp.as_file().set_permissions(permissions)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path)?;
+    Ok(())
+}
+
+/// Rotates the file to the kms `key`, replacing the keys planned and keeping the other recipients.
+/// `sops -r` decrypts and encrypts the file in memory and prints it, then the result replaces the
+/// original. If anything fails or the process is killed, the file is left as it was.
+async fn rotate_file(key: &str, planned: &PlannedRotation) -> Result<()> {
+    let path = &planned.path;
+    let sops = read_sops(path)?.ok_or_else(|| eyre!("not encrypted by sops"))?;
+
+    // sops can't guess the format of every extension
+    let mut rotate = Command::new("sops");
+    rotate.args(["-r", "--input-type", "yaml", "--output-type", "yaml"]);
+    if sops.get_key_type(key).is_none() {
+        rotate.args(["--add-kms", key]);
+    }
+    for replaced in &planned.replace {
+        let key_type = sops
+            .get_key_type(replaced)
+            .ok_or_else(|| eyre!("not encrypted with {replaced}"))?;
+        rotate.arg(format!("--rm-{key_type}")).arg(replaced);
+    }
+    rotate.arg(path);
+    let ciphertext = run_sops(&mut rotate).await.wrap_err("could not rotate")?;
+    replace_file(path, &ciphertext).wrap_err("could not replace the file")
+}
+
+/// Rotates the files in the plan, see `rotate_file`.
+/// A file is only rotated if it is still encrypted with the keys it was when the plan was made,
+/// so exactly what was reviewed is applied.
+pub async fn apply_rotation_plan(plan: &RotationPlan) -> Result<Vec<Rotation>> {
+    let mut rotations = vec![];
+    for planned in &plan.files {
+        let rotated = async {
+            check_planned_keys(planned)?;
+            rotate_file(&plan.key, planned).await
+        };
+        let status = match rotated.await {
+            Ok(()) => RotationStatus::Rotated,
+            Err(e) => RotationStatus::Failed(format!("{e:#}")),
+        };
+        rotations.push(Rotation {
+            path: planned.path.clone(),
+            status,
+        });
+    }
+    Ok(rotations)
+}
+
+/// Checks the file is still encrypted with the keys it was when the plan was made.
+fn check_planned_keys(planned: &PlannedRotation) -> Result<()> {
+    let mut keys = read_sops(&planned.path)?
+        .ok_or_else(|| eyre!("not encrypted by sops"))?
+        .get_key_ids();
+    keys.sort();
+    if keys != planned.from {
+        return Err(eyre!(
+            "changed since the plan was made, now encrypted with {}",
+            keys.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+pub async fn get_dup_documents(scan: &Scan) -> Result<BTreeMap<ResourceId, Vec<Definition>>> {
+    let mut documents = BTreeMap::<ResourceId, Vec<Definition>>::new();
+    for d in &scan.documents {
+        // More than one definition probably means the document is duped
+        documents
+            .entry(d.document.get_id())
+            .or_default()
+            .push(Definition {
+                path: d.path.clone(),
+                index: d.index,
+                position: d
+                    .get_field_position("metadata.name")
+                    .unwrap_or_else(|| d.get_position()),
+                sops: d.document.sops.clone(),
+            });
+    }
 
     Ok(documents)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "arn:aws:kms:us-east-1:111:key/a";
+    const KEY_B: &str = "arn:aws:kms:eu-west-1:111:key/b";
+    const ROLE: &str = "arn:aws:iam::111:role/r";
+    const NEW_KEY: &str = "arn:aws:kms:us-east-1:111:key/new";
+
+    fn with_role(arn: &str) -> String {
+        format!("{arn}+{ROLE}")
+    }
+
+    /// The result of `get_kms_keys` for files encrypted with the keys.
+    fn keys_used(files: &[(&str, &[&str])]) -> BTreeMap<String, BTreeSet<PathBuf>> {
+        let mut keys_used = BTreeMap::<String, BTreeSet<PathBuf>>::new();
+        for (path, keys) in files {
+            for key in *keys {
+                keys_used
+                    .entry(key.to_string())
+                    .or_default()
+                    .insert(PathBuf::from(path));
+            }
+        }
+        keys_used
+    }
+
+    #[test]
+    fn key_matchers() {
+        let exact = KeyMatcher::parse_key(KEY_A).unwrap();
+        assert!(matches!(exact, KeyMatcher::Exact(_)));
+        assert!(exact.matches(KEY_A));
+        assert!(!exact.matches(KEY_B));
+
+        let glob = KeyMatcher::parse_key("arn:aws:kms:us-east-1:*").unwrap();
+        assert!(matches!(glob, KeyMatcher::Glob(_)));
+        assert!(glob.matches(KEY_A));
+        assert!(!glob.matches(KEY_B));
+
+        let regex = KeyMatcher::parse_regex("^arn:aws:kms:[^:]+:111:key/b$").unwrap();
+        assert!(regex.matches(KEY_B));
+        assert!(!regex.matches(KEY_A));
+        assert!(KeyMatcher::parse_regex("(").is_err());
+    }
+
+    #[test]
+    fn key_matchers_match_the_arn_of_roles() {
+        let key = with_role(KEY_A);
+        assert!(KeyMatcher::parse_key(KEY_A).unwrap().matches(&key));
+        assert!(KeyMatcher::parse_key(&key).unwrap().matches(&key));
+        assert!(KeyMatcher::parse_regex("^arn:aws:kms:us-east-1:111:key/a$")
+            .unwrap()
+            .matches(&key));
+        assert!(!KeyMatcher::parse_key(KEY_B).unwrap().matches(&key));
+        // A key with a role is not the key without it
+        assert!(!KeyMatcher::parse_key(&key).unwrap().matches(KEY_A));
+        // Only kms keys have a role
+        assert!(!KeyMatcher::parse_key("age1x").unwrap().matches("age1x+y"));
+    }
+
+    #[test]
+    fn plan_replaces_every_key_without_from() {
+        let keys_used = keys_used(&[
+            ("a.yaml", &[KEY_A, "age1keep"]),
+            ("new.yaml", &[NEW_KEY]),
+            ("both.yaml", &[KEY_B, NEW_KEY]),
+        ]);
+        let plan = plan_rotation(NEW_KEY, Path::new(""), &keys_used, &[]);
+        let planned: Vec<(&Path, &[String])> = plan
+            .get_files()
+            .iter()
+            .map(|f| (f.get_path().as_path(), f.get_replace()))
+            .collect();
+        assert_eq!(
+            planned,
+            [
+                (
+                    Path::new("a.yaml"),
+                    &["age1keep".to_string(), KEY_A.to_string()][..]
+                ),
+                (Path::new("both.yaml"), &[KEY_B.to_string()][..]),
+            ]
+        );
+        assert_eq!(plan.get_files()[1].get_to(NEW_KEY), [NEW_KEY]);
+    }
+
+    #[test]
+    fn plan_only_replaces_the_keys_from() {
+        let key_a_role = with_role(KEY_A);
+        let keys_used = keys_used(&[
+            ("a.yaml", &[KEY_A, "age1keep"]),
+            ("role.yaml", &[&key_a_role, KEY_B]),
+            ("b.yaml", &[KEY_B]),
+        ]);
+        let from = [KeyMatcher::parse_key(KEY_A).unwrap()];
+        let plan = plan_rotation(NEW_KEY, Path::new(""), &keys_used, &from);
+        let files = plan.get_files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].get_path(), Path::new("a.yaml"));
+        assert_eq!(files[0].get_replace(), [KEY_A]);
+        assert_eq!(files[0].get_to(NEW_KEY), ["age1keep", NEW_KEY]);
+        assert_eq!(files[1].get_path(), Path::new("role.yaml"));
+        assert_eq!(files[1].get_replace(), [key_a_role]);
+        assert_eq!(files[1].get_to(NEW_KEY), [KEY_B, NEW_KEY]);
+    }
+
+    #[test]
+    fn plan_never_replaces_the_new_key() {
+        let keys_used = keys_used(&[("a.yaml", &[KEY_A, NEW_KEY]), ("new.yaml", &[NEW_KEY])]);
+        let from = [KeyMatcher::parse_key("arn:aws:kms:us-east-1:*").unwrap()];
+        let plan = plan_rotation(NEW_KEY, Path::new(""), &keys_used, &from);
+        assert_eq!(plan.get_files().len(), 1);
+        assert_eq!(plan.get_files()[0].get_replace(), [KEY_A]);
+    }
+
+    #[tokio::test]
+    async fn plan_is_applied_from_the_plan_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(repo.join("apps")).unwrap();
+        std::fs::create_dir_all(dir.path().join("plans")).unwrap();
+        let secret = repo.join("apps/creds.yaml");
+        std::fs::write(
+            &secret,
+            format!(
+                "apiVersion: v1\nkind: Secret\nmetadata:\n  name: creds\n\
+                 data:\n  password: ENC[abc]\nsops:\n  kms:\n    - arn: {KEY_A}\n      enc: xx\n"
+            ),
+        )
+        .unwrap();
+
+        let paths = find_files(&repo, Path::new(""), &["**/*.yaml".to_string()], &[]).unwrap();
+        let keys_used = get_kms_keys(&scan_documents(&paths).await).await.unwrap();
+        let plan = plan_rotation(NEW_KEY, &repo, &keys_used, &[]);
+        let plan_path = dir.path().join("plans/plan.yml");
+        plan.write(&plan_path).unwrap();
+
+        // The paths are written relative to the plan file
+        let written: Value = serde_yaml::from_reader(File::open(&plan_path).unwrap()).unwrap();
+        assert_eq!(written["root"], "../repo");
+        assert_eq!(written["files"][0]["path"], "apps/creds.yaml");
+
+        let read = RotationPlan::read(&plan_path).unwrap();
+        assert_eq!(read.get_key(), NEW_KEY);
+        assert_eq!(read.get_files().len(), 1);
+        let planned = &read.get_files()[0];
+        assert_eq!(
+            planned.get_path().canonicalize().unwrap(),
+            secret.canonicalize().unwrap()
+        );
+        assert_eq!(planned.get_from(), [KEY_A]);
+        // What is checked before rotating a file of the plan
+        check_planned_keys(planned).unwrap();
+    }
+
+    #[test]
+    fn relative_paths() {
+        let path = |from, to| relative_path(Path::new(from), Path::new(to));
+        assert_eq!(path("/repo/plans", "/repo/apps"), Path::new("../apps"));
+        assert_eq!(path("/repo", "/repo/apps"), Path::new("apps"));
+        assert_eq!(path("/repo/apps", "/repo"), Path::new(".."));
+        assert_eq!(path("/repo", "/repo"), Path::new("."));
+    }
+
+    /// A directory with yaml files at several depths, and a file that is not yaml.
+    fn files_tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "a.yml",
+            "b.yaml",
+            "c.json",
+            "apps/d.yml",
+            "apps/charts/e.yaml",
+            "apps/charts/sub/f.yml",
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    /// The files found, relative to `dir`.
+    fn found(dir: &Path, prefix: &str, include: &[&str], exclude: &[&str]) -> Vec<String> {
+        let globs = |g: &[&str]| g.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        find_files(dir, Path::new(prefix), &globs(include), &globs(exclude))
+            .unwrap()
+            .iter()
+            .map(|p| {
+                p.strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_files_default_globs() {
+        let dir = files_tree();
+        assert_eq!(
+            found(dir.path(), "", DEFAULT_INCLUDE, &[]),
+            [
+                "a.yml",
+                "apps/charts/e.yaml",
+                "apps/charts/sub/f.yml",
+                "apps/d.yml",
+                "b.yaml"
+            ]
+        );
+    }
+
+    #[test]
+    fn find_files_star_does_not_cross_directories() {
+        let dir = files_tree();
+        assert_eq!(found(dir.path(), "", &["*.yml"], &[]), ["a.yml"]);
+        assert_eq!(found(dir.path(), "", &["apps/*"], &[]), ["apps/d.yml"]);
+        assert_eq!(
+            found(dir.path(), "", &["**/*.yml"], &[]),
+            ["a.yml", "apps/charts/sub/f.yml", "apps/d.yml"]
+        );
+        assert_eq!(
+            found(dir.path(), "", &["apps/**/*.yaml"], &[]),
+            ["apps/charts/e.yaml"]
+        );
+    }
+
+    #[test]
+    fn find_files_exclude() {
+        let dir = files_tree();
+        assert_eq!(
+            found(dir.path(), "", DEFAULT_INCLUDE, &["apps/charts/*"]),
+            ["a.yml", "apps/charts/sub/f.yml", "apps/d.yml", "b.yaml"]
+        );
+        assert_eq!(
+            found(
+                dir.path(),
+                "",
+                DEFAULT_INCLUDE,
+                &["apps/charts/**", "*.yaml"]
+            ),
+            ["a.yml", "apps/d.yml"]
+        );
+    }
+
+    #[test]
+    fn find_files_with_a_prefix() {
+        let dir = files_tree();
+        let apps = dir.path().join("apps");
+        // The globs are relative to the parent of `apps`
+        let found = |include: &[&str], exclude: &[&str]| {
+            found(&apps, "apps", include, exclude)
+                .into_iter()
+                .map(|p| format!("apps/{p}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(found(DEFAULT_INCLUDE, &["apps/charts/**"]), ["apps/d.yml"]);
+        assert!(found(&["*.yml"], &[]).is_empty());
+    }
+
+    #[test]
+    fn find_files_invalid_glob() {
+        let dir = files_tree();
+        let include = ["[".to_string()];
+        assert!(find_files(dir.path(), Path::new(""), &include, &[]).is_err());
+    }
+}
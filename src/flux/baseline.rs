@@ -0,0 +1,230 @@
+use super::*;
+
+/// Bumped whenever the fingerprints change, a baseline of another version can't be read.
+pub const BASELINE_VERSION: u32 = 1;
+
+/// The findings known when the baseline was written, only the others are reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Baseline {
+    version: u32,
+    findings: Vec<BaselineEntry>,
+}
+
+/// The findings of a baseline with the same fingerprint, e.g. two missing dependencies of an object.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    #[serde(flatten)]
+    fingerprint: Fingerprint,
+    /// How many findings had the fingerprint, only as many are not reported
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    count: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+fn is_one(count: &usize) -> bool {
+    *count == 1
+}
+
+/// Identifies a finding across runs, without its position or message that change with every edit.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// The rule id, kept as a string so rules that are gone can still be read
+    rule: String,
+    /// The resource the finding is about, e.g. `Secret apps/creds`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resource: Option<String>,
+    /// The files of the finding, relative to the validated directory and sorted
+    paths: Vec<String>,
+}
+
+impl Fingerprint {
+    /// The fingerprint of a finding, its paths are made relative to `root`.
+    pub fn new(finding: &Finding, root: &Path) -> Self {
+        let paths: BTreeSet<String> = finding
+            .get_locations()
+            .iter()
+            .map(|l| {
+                let path = l.get_path();
+                let path = path.strip_prefix(root).unwrap_or(path);
+                // The same on every OS
+                path.to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        Fingerprint {
+            rule: finding.get_rule().get_id().to_string(),
+            resource: finding.get_resource().map(|r| r.to_string()),
+            paths: paths.into_iter().collect(),
+        }
+    }
+
+    pub fn get_rule_id(&self) -> &str {
+        &self.rule
+    }
+
+    pub fn get_resource(&self) -> Option<&str> {
+        self.resource.as_deref()
+    }
+
+    pub fn get_paths(&self) -> &[String] {
+        &self.paths
+    }
+}
+
+impl BaselineEntry {
+    pub fn get_fingerprint(&self) -> &Fingerprint {
+        &self.fingerprint
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Baseline {
+    /// A baseline of every finding of the report, found under `root`.
+    pub fn new(report: &Report, root: &Path) -> Self {
+        let mut counts = BTreeMap::<Fingerprint, usize>::new();
+        for f in report.get_findings() {
+            *counts.entry(Fingerprint::new(f, root)).or_default() += 1;
+        }
+        Baseline {
+            version: BASELINE_VERSION,
+            findings: counts
+                .into_iter()
+                .map(|(fingerprint, count)| BaselineEntry { fingerprint, count })
+                .collect(),
+        }
+    }
+
+    /// Splits the findings, found under `root`, into the ones known when the baseline was written
+    /// and the new ones. A fingerprint only matches as many findings as it had in the baseline,
+    /// a second missing dependency of an object is new.
+    pub fn partition(&self, findings: Vec<Finding>, root: &Path) -> (Vec<Finding>, Vec<Finding>) {
+        let mut remaining: BTreeMap<&Fingerprint, usize> = self
+            .findings
+            .iter()
+            .map(|e| (&e.fingerprint, e.count))
+            .collect();
+        findings
+            .into_iter()
+            .partition(|f| match remaining.get_mut(&Fingerprint::new(f, root)) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                _ => false,
+            })
+    }
+
+    pub fn get_findings(&self) -> &[BaselineEntry] {
+        &self.findings
+    }
+
+    /// The number of findings in the baseline.
+    pub fn count_findings(&self) -> usize {
+        self.findings.iter().map(|e| e.count).sum()
+    }
+
+    /// Reads a baseline written by `Baseline::write`, of the current version.
+    pub fn read(path: &Path) -> Result<Self> {
+        let f = File::open(path).wrap_err_with(|| format!("could not open {}", path.display()))?;
+        let baseline: Baseline = serde_yaml::from_reader(f)
+            .wrap_err_with(|| format!("invalid baseline {}", path.display()))?;
+        if baseline.version != BASELINE_VERSION {
+            return Err(eyre!(
+                "unsupported version {} of the baseline {}, expected {BASELINE_VERSION}. \
+                 Write it again with --write-baseline",
+                baseline.version,
+                path.display()
+            ));
+        }
+        Ok(baseline)
+    }
+
+    /// Writes the baseline as YAML, sorted so it can be reviewed and diffed.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let f =
+            File::create(path).wrap_err_with(|| format!("could not create {}", path.display()))?;
+        Ok(serde_yaml::to_writer(f, self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KUSTOMIZATION: &str = "\
+apiVersion: source.toolkit.fluxcd.io/v1
+kind: GitRepository
+metadata:
+  name: flux-system
+  namespace: flux-system
+spec:
+  interval: 10m
+  url: https://github.com/example/fleet
+---
+apiVersion: kustomize.toolkit.fluxcd.io/v1
+kind: Kustomization
+metadata:
+  name: apps
+  namespace: flux-system
+spec:
+  interval: 10m
+  sourceRef:
+    kind: GitRepository
+    name: flux-system
+  dependsOn:
+";
+
+    async fn report(root: &Path, depends_on: &[&str]) -> Report {
+        let path = root.join("apps.yaml");
+        let mut content = KUSTOMIZATION.to_string();
+        for name in depends_on {
+            content.push_str(&format!("    - name: {name}\n"));
+        }
+        std::fs::write(&path, content).unwrap();
+        let scan = scan_documents(&vec![path]).await;
+        Report::new(&scan, &BTreeMap::new(), &BTreeMap::new())
+    }
+
+    #[tokio::test]
+    async fn findings_with_the_same_fingerprint_are_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = Baseline::new(&report(dir.path(), &["infra"]).await, dir.path());
+        assert_eq!(baseline.count_findings(), 1);
+
+        let mut report = report(dir.path(), &["infra", "monitoring"]).await;
+        report.apply_baseline(&baseline, dir.path());
+        assert_eq!(report.get_baselined().len(), 1);
+        assert_eq!(report.get_findings().len(), 1);
+        assert_eq!(report.get_findings()[0].get_rule(), Rule::MissingDependency);
+    }
+
+    #[tokio::test]
+    async fn count_is_kept_in_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = report(dir.path(), &["infra", "monitoring"]).await;
+        let baseline = Baseline::new(&report, dir.path());
+        assert_eq!(baseline.get_findings().len(), 1);
+        assert_eq!(baseline.get_findings()[0].get_count(), 2);
+
+        let path = dir.path().join("baseline.yml");
+        baseline.write(&path).unwrap();
+        assert_eq!(Baseline::read(&path).unwrap(), baseline);
+    }
+
+    #[test]
+    fn other_versions_are_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baseline.yml");
+        std::fs::write(&path, "version: 2\nfindings: []\n").unwrap();
+        let error = Baseline::read(&path).unwrap_err().to_string();
+        assert!(
+            error.starts_with("unsupported version 2 of the baseline"),
+            "{error}"
+        );
+    }
+}
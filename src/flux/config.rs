@@ -0,0 +1,298 @@
+use super::*;
+
+/// The name of the config file, looked for in the validated directory and its parents.
+pub const CONFIG_FILE: &str = ".flux-validator.toml";
+
+/// The files validated when no include glob is given.
+pub const DEFAULT_INCLUDE: &[&str] = &["**/*.yml", "**/*.yaml"];
+
+/// The settings of a repo, read from `.flux-validator.toml`.
+/// Everything is optional, what is absent is left to the defaults or the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Globs of the files to check, relative to the directory of the config file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    include: Option<Vec<String>>,
+    /// Globs of the files to skip, relative to the directory of the config file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exclude: Option<Vec<String>>,
+    /// The keys sops files can be encrypted with, can be globs. Any key is allowed when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_keys: Option<Vec<String>>,
+    /// The rules to change, by id
+    #[serde(
+        skip_serializing_if = "BTreeMap::is_empty",
+        serialize_with = "serialize_rules",
+        deserialize_with = "deserialize_rules"
+    )]
+    rules: BTreeMap<Rule, RuleConfig>,
+    output: OutputConfig,
+}
+
+/// TOML only has string keys, the rules are keyed by id.
+fn serialize_rules<S: serde::Serializer>(
+    rules: &BTreeMap<Rule, RuleConfig>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(rules.iter().map(|(rule, config)| (rule.get_id(), config)))
+}
+
+fn deserialize_rules<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Rule, RuleConfig>, D::Error> {
+    let rules = BTreeMap::<String, RuleConfig>::deserialize(deserializer)?;
+    rules
+        .into_iter()
+        .map(|(id, config)| match Rule::from_id(&id) {
+            Some(rule) => Ok((rule, config)),
+            None => {
+                let ids: Vec<&str> = Rule::ALL.iter().map(|r| r.get_id()).collect();
+                Err(serde::de::Error::custom(format!(
+                    "unknown rule `{id}`, expected one of {}",
+                    ids.join(", ")
+                )))
+            }
+        })
+        .collect()
+}
+
+/// Turns a rule off or changes the severity of its findings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<Severity>,
+}
+
+/// The defaults of the command line output.
+/// Kept as written, the values are checked by the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OutputConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fail_on: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    absolute_paths: Option<bool>,
+}
+
+impl Config {
+    /// Finds the config file of a directory, in the directory or the closest parent with one.
+    pub fn find(dir: &Path) -> Result<Option<PathBuf>> {
+        let dir = dir
+            .canonicalize()
+            .wrap_err_with(|| format!("Could not find {}", dir.display()))?;
+        Ok(dir
+            .ancestors()
+            .map(|d| d.join(CONFIG_FILE))
+            .find(|p| p.is_file()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Could not read {}", path.display()))?;
+        toml::from_str(&content).wrap_err_with(|| format!("Invalid config {}", path.display()))
+    }
+
+    /// Reads the config of a directory, along with where it was found.
+    /// The config is empty when there is no config file.
+    pub fn discover(dir: &Path) -> Result<(Option<PathBuf>, Self)> {
+        match Config::find(dir)? {
+            Some(path) => {
+                let config = Config::read(&path)?;
+                Ok((Some(path), config))
+            }
+            None => Ok((None, Config::default())),
+        }
+    }
+
+    /// Where `dir` is from the directory of the config file at `path`, e.g. `apps` when the config
+    /// is in the parent of `apps`. The globs of the config are relative to its directory, so the
+    /// files of `dir` are matched with this in front of them. Empty when there is no config file.
+    pub fn glob_prefix(path: Option<&Path>, dir: &Path) -> Result<PathBuf> {
+        let root = match path.and_then(Path::parent) {
+            Some(root) => root,
+            None => return Ok(PathBuf::new()),
+        };
+        let dir = dir
+            .canonicalize()
+            .wrap_err_with(|| format!("Could not find {}", dir.display()))?;
+        // The config is found in the directory or one of its parents
+        Ok(dir.strip_prefix(root).unwrap_or(&dir).to_path_buf())
+    }
+
+    /// Puts `prefix` in front of the include and exclude globs, to match globs relative to a
+    /// directory against paths relative to the directory of the config file.
+    pub fn with_glob_prefix(mut self, prefix: &Path) -> Self {
+        if prefix.as_os_str().is_empty() {
+            return self;
+        }
+        let prefix = glob::Pattern::escape(&prefix.to_string_lossy().replace('\\', "/"));
+        let rebase = |globs: Vec<String>| globs.iter().map(|g| format!("{prefix}/{g}")).collect();
+        self.include = self.include.map(rebase);
+        self.exclude = self.exclude.map(rebase);
+        self
+    }
+
+    /// Overrides the settings with the ones set in `other`, e.g. the command line.
+    pub fn merge(mut self, other: Config) -> Self {
+        self.include = other.include.or(self.include);
+        self.exclude = other.exclude.or(self.exclude);
+        self.allowed_keys = other.allowed_keys.or(self.allowed_keys);
+        for (rule, config) in other.rules {
+            let current = self.rules.entry(rule).or_default();
+            current.enabled = config.enabled.or(current.enabled);
+            current.severity = config.severity.or(current.severity);
+        }
+        self.output = OutputConfig {
+            format: other.output.format.or(self.output.format),
+            fail_on: other.output.fail_on.or(self.output.fail_on),
+            absolute_paths: other.output.absolute_paths.or(self.output.absolute_paths),
+        };
+        self
+    }
+
+    /// The config with the defaults filled in, every rule is listed.
+    /// The output is left as is, its defaults are the command line's.
+    pub fn effective(&self) -> Self {
+        Config {
+            include: Some(self.get_include()),
+            exclude: Some(self.get_exclude().to_vec()),
+            allowed_keys: self.allowed_keys.clone(),
+            rules: Rule::ALL
+                .iter()
+                .map(|rule| {
+                    let config = RuleConfig {
+                        enabled: Some(self.is_enabled(*rule)),
+                        severity: Some(self.get_severity(*rule)),
+                    };
+                    (*rule, config)
+                })
+                .collect(),
+            output: self.output.clone(),
+        }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn set_include(&mut self, include: Vec<String>) {
+        self.include = Some(include);
+    }
+
+    pub fn set_exclude(&mut self, exclude: Vec<String>) {
+        self.exclude = Some(exclude);
+    }
+
+    pub fn set_output(&mut self, output: OutputConfig) {
+        self.output = output;
+    }
+
+    /// The include globs, `DEFAULT_INCLUDE` when none are set.
+    pub fn get_include(&self) -> Vec<String> {
+        match &self.include {
+            Some(include) => include.clone(),
+            None => DEFAULT_INCLUDE.iter().map(|g| g.to_string()).collect(),
+        }
+    }
+
+    pub fn get_exclude(&self) -> &[String] {
+        self.exclude.as_deref().unwrap_or_default()
+    }
+
+    /// The keys allowed to encrypt with, `None` when any key is allowed.
+    pub fn get_allowed_keys(&self) -> Option<&[String]> {
+        self.allowed_keys.as_deref()
+    }
+
+    /// Rules are enabled unless turned off.
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        self.rules
+            .get(&rule)
+            .and_then(|r| r.enabled)
+            .unwrap_or(true)
+    }
+
+    /// The severity of the findings of the rule, the rule's own unless changed.
+    pub fn get_severity(&self, rule: Rule) -> Severity {
+        self.rules
+            .get(&rule)
+            .and_then(|r| r.severity)
+            .unwrap_or_else(|| rule.get_severity())
+    }
+
+    pub fn get_output(&self) -> &OutputConfig {
+        &self.output
+    }
+}
+
+impl OutputConfig {
+    pub fn new(
+        format: Option<String>,
+        fail_on: Option<String>,
+        absolute_paths: Option<bool>,
+    ) -> Self {
+        OutputConfig {
+            format,
+            fail_on,
+            absolute_paths,
+        }
+    }
+
+    pub fn get_format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    pub fn get_fail_on(&self) -> Option<&str> {
+        self.fail_on.as_deref()
+    }
+
+    pub fn get_absolute_paths(&self) -> Option<bool> {
+        self.absolute_paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_prefix_of_a_config_in_a_parent() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path().canonicalize().unwrap();
+        let apps = root.join("apps/prod");
+        std::fs::create_dir_all(&apps).unwrap();
+        let path = root.join(CONFIG_FILE);
+        std::fs::write(&path, "exclude = [\"apps/prod/charts/**\"]\n").unwrap();
+
+        assert_eq!(Config::find(&apps).unwrap(), Some(path.clone()));
+        assert_eq!(
+            Config::glob_prefix(Some(&path), &apps).unwrap(),
+            Path::new("apps/prod")
+        );
+        assert_eq!(
+            Config::glob_prefix(Some(&path), &root).unwrap(),
+            Path::new("")
+        );
+        assert_eq!(Config::glob_prefix(None, &apps).unwrap(), Path::new(""));
+    }
+
+    #[test]
+    fn glob_prefix_is_put_in_front_of_the_globs() {
+        let mut config = Config::default();
+        config.set_exclude(vec!["charts/**".to_string()]);
+        let config = config.with_glob_prefix(Path::new("apps/[prod]"));
+        // Only the globs that are set, the default include is relative to any directory
+        assert_eq!(config.get_include(), DEFAULT_INCLUDE);
+        assert_eq!(config.get_exclude(), ["apps/[[]prod[]]/charts/**"]);
+        assert_eq!(
+            config.with_glob_prefix(Path::new("")).get_exclude(),
+            ["apps/[[]prod[]]/charts/**"]
+        );
+    }
+}
@@ -0,0 +1,983 @@
+use super::*;
+
+/// The Flux objects the validator knows, by api group, kind and the versions of them.
+/// Objects of other versions, e.g. long removed alphas, are left untyped.
+const FLUX_KINDS: &[(&str, &str, &[&str])] = &[
+    (
+        "kustomize.toolkit.fluxcd.io",
+        "Kustomization",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "helm.toolkit.fluxcd.io",
+        "HelmRelease",
+        &["v2beta1", "v2beta2", "v2"],
+    ),
+    (
+        "source.toolkit.fluxcd.io",
+        "GitRepository",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "source.toolkit.fluxcd.io",
+        "HelmRepository",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "source.toolkit.fluxcd.io",
+        "OCIRepository",
+        &["v1beta2", "v1"],
+    ),
+    (
+        "source.toolkit.fluxcd.io",
+        "Bucket",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "image.toolkit.fluxcd.io",
+        "ImageRepository",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "image.toolkit.fluxcd.io",
+        "ImagePolicy",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "image.toolkit.fluxcd.io",
+        "ImageUpdateAutomation",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+    (
+        "notification.toolkit.fluxcd.io",
+        "Alert",
+        &["v1beta1", "v1beta2", "v1beta3"],
+    ),
+    (
+        "notification.toolkit.fluxcd.io",
+        "Provider",
+        &["v1beta1", "v1beta2", "v1beta3"],
+    ),
+    (
+        "notification.toolkit.fluxcd.io",
+        "Receiver",
+        &["v1beta1", "v1beta2", "v1"],
+    ),
+];
+
+/// The api group of the sources, e.g. `GitRepository`.
+pub const SOURCE_GROUP: &str = "source.toolkit.fluxcd.io";
+
+/// A Flux object, with its spec read into the model of its kind.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluxObject {
+    Kustomization(KustomizationSpec),
+    /// Boxed, it is much larger than the other specs
+    HelmRelease(Box<HelmReleaseSpec>),
+    GitRepository(GitRepositorySpec),
+    HelmRepository(HelmRepositorySpec),
+    OCIRepository(OCIRepositorySpec),
+    Bucket(BucketSpec),
+    ImageRepository(ImageRepositorySpec),
+    ImagePolicy(ImagePolicySpec),
+    ImageUpdateAutomation(ImageUpdateAutomationSpec),
+    Alert(AlertSpec),
+    Provider(ProviderSpec),
+    Receiver(ReceiverSpec),
+}
+
+/// Only the spec is read, the rest is in the `Document`.
+#[derive(Deserialize)]
+struct Object<T> {
+    spec: T,
+}
+
+/// A reference to an object in the same namespace, e.g. a `secretRef`.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct LocalObjectReference {
+    name: String,
+}
+
+/// A reference to an object of a known kind, e.g. an item of `dependsOn`.
+/// The namespace of the referencing object is used when there is none.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct NamespacedObjectReference {
+    name: String,
+    namespace: Option<String>,
+}
+
+/// A reference to an object of any kind, e.g. a `sourceRef`.
+/// The namespace of the referencing object is used when there is none.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CrossNamespaceObjectReference {
+    api_version: Option<String>,
+    kind: String,
+    name: String,
+    namespace: Option<String>,
+}
+
+/// How a `Kustomization` decrypts the secrets it applies.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Decryption {
+    /// Only `sops` is supported by Flux
+    provider: String,
+    secret_ref: Option<LocalObjectReference>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KustomizationSpec {
+    interval: String,
+    source_ref: CrossNamespaceObjectReference,
+    /// The path in the source, its root when absent
+    path: Option<String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    depends_on: Vec<NamespacedObjectReference>,
+    #[serde(default)]
+    prune: bool,
+    #[serde(default)]
+    suspend: bool,
+    target_namespace: Option<String>,
+    service_account_name: Option<String>,
+    decryption: Option<Decryption>,
+}
+
+/// The chart a `HelmRelease` creates a `HelmChart` for.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct HelmChartTemplate {
+    spec: HelmChartTemplateSpec,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HelmChartTemplateSpec {
+    /// The name of the chart, or its path in a `GitRepository` or a `Bucket`
+    chart: String,
+    version: Option<String>,
+    source_ref: CrossNamespaceObjectReference,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    values_files: Vec<String>,
+}
+
+/// A `ConfigMap` or a `Secret` with values of a `HelmRelease`.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValuesReference {
+    kind: String,
+    name: String,
+    values_key: Option<String>,
+    target_path: Option<String>,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HelmReleaseSpec {
+    interval: String,
+    /// Absent when the chart is referenced with `chart_ref`
+    chart: Option<HelmChartTemplate>,
+    /// A `HelmChart` or an `OCIRepository`, since `v2`
+    chart_ref: Option<CrossNamespaceObjectReference>,
+    release_name: Option<String>,
+    target_namespace: Option<String>,
+    storage_namespace: Option<String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    depends_on: Vec<NamespacedObjectReference>,
+    #[serde(default)]
+    suspend: bool,
+    service_account_name: Option<String>,
+    values: Option<Value>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    values_from: Vec<ValuesReference>,
+}
+
+/// What to check out of a git repository, the default branch when everything is absent.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(default)]
+pub struct GitRepositoryRef {
+    branch: Option<String>,
+    tag: Option<String>,
+    semver: Option<String>,
+    /// A reference name, e.g. `refs/pull/1/head`
+    name: Option<String>,
+    commit: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GitRepositorySpec {
+    url: String,
+    interval: String,
+    #[serde(rename = "ref")]
+    reference: Option<GitRepositoryRef>,
+    secret_ref: Option<LocalObjectReference>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HelmRepositorySpec {
+    url: String,
+    /// Not needed by `oci` repositories
+    interval: Option<String>,
+    /// `default` or `oci`
+    #[serde(rename = "type")]
+    repository_type: Option<String>,
+    secret_ref: Option<LocalObjectReference>,
+    provider: Option<String>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+/// What to pull from an OCI repository, the `latest` tag when everything is absent.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(default)]
+pub struct OCIRepositoryRef {
+    digest: Option<String>,
+    semver: Option<String>,
+    tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OCIRepositorySpec {
+    url: String,
+    interval: String,
+    #[serde(rename = "ref")]
+    reference: Option<OCIRepositoryRef>,
+    secret_ref: Option<LocalObjectReference>,
+    provider: Option<String>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketSpec {
+    bucket_name: String,
+    endpoint: String,
+    interval: String,
+    /// `generic`, `aws`, `gcp` or `azure`
+    provider: Option<String>,
+    region: Option<String>,
+    prefix: Option<String>,
+    secret_ref: Option<LocalObjectReference>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageRepositorySpec {
+    image: String,
+    interval: Option<String>,
+    secret_ref: Option<LocalObjectReference>,
+    provider: Option<String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    exclusion_list: Vec<String>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+/// How an `ImagePolicy` picks the latest tag, only one of them is set.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(default)]
+pub struct ImagePolicyChoice {
+    semver: Option<SemVerPolicy>,
+    alphabetical: Option<OrderPolicy>,
+    numerical: Option<OrderPolicy>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct SemVerPolicy {
+    range: String,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct OrderPolicy {
+    /// `asc` or `desc`
+    order: Option<String>,
+}
+
+/// The tags an `ImagePolicy` picks from.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct TagFilter {
+    pattern: String,
+    extract: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagePolicySpec {
+    image_repository_ref: NamespacedObjectReference,
+    policy: ImagePolicyChoice,
+    filter_tags: Option<TagFilter>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+pub struct UpdateStrategy {
+    /// Only `Setters` is supported by Flux
+    strategy: String,
+    path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageUpdateAutomationSpec {
+    /// A `GitRepository`, the kind can be left out
+    source_ref: ImageSourceReference,
+    interval: String,
+    /// Where to check out, commit and push, kept as is
+    git: Option<Value>,
+    update: Option<UpdateStrategy>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+/// The `sourceRef` of an `ImageUpdateAutomation`, the same as `CrossNamespaceObjectReference`
+/// except the kind defaults to `GitRepository`.
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ImageSourceReference {
+    api_version: Option<String>,
+    #[serde(default = "git_repository_kind")]
+    kind: String,
+    name: String,
+    namespace: Option<String>,
+}
+
+fn git_repository_kind() -> String {
+    "GitRepository".to_string()
+}
+
+impl From<ImageSourceReference> for CrossNamespaceObjectReference {
+    fn from(r: ImageSourceReference) -> Self {
+        CrossNamespaceObjectReference {
+            api_version: r.api_version,
+            kind: r.kind,
+            name: r.name,
+            namespace: r.namespace,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertSpec {
+    provider_ref: LocalObjectReference,
+    /// `info` or `error`
+    event_severity: Option<String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    event_sources: Vec<CrossNamespaceObjectReference>,
+    summary: Option<String>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSpec {
+    /// e.g. `slack`, `github` or `generic`
+    #[serde(rename = "type")]
+    provider_type: String,
+    channel: Option<String>,
+    address: Option<String>,
+    secret_ref: Option<LocalObjectReference>,
+    #[serde(default)]
+    suspend: bool,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiverSpec {
+    /// e.g. `github`, `gitlab` or `generic`
+    #[serde(rename = "type")]
+    receiver_type: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    events: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_empty")]
+    resources: Vec<CrossNamespaceObjectReference>,
+    secret_ref: LocalObjectReference,
+    #[serde(default)]
+    suspend: bool,
+}
+
+/// Reads the spec of an object.
+fn spec<T: serde::de::DeserializeOwned>(value: &Value) -> Result<T> {
+    Ok(serde_yaml::from_value::<Object<T>>(value.clone())?.spec)
+}
+
+impl FluxObject {
+    /// Reads a k8s object into its Flux model, keyed on its `apiVersion` and `kind`.
+    /// `None` when it is not a Flux object, or not of a version known to the validator.
+    pub fn from_value(value: &Value) -> Result<Option<Self>> {
+        let field = |name: &str| value.get(name).and_then(Value::as_str).unwrap_or_default();
+        let (api_version, kind) = (field("apiVersion"), field("kind"));
+        let known = match api_version.split_once('/') {
+            Some((group, version)) => FLUX_KINDS
+                .iter()
+                .any(|(g, k, versions)| *g == group && *k == kind && versions.contains(&version)),
+            None => false,
+        };
+        if !known {
+            return Ok(None);
+        }
+        let object = match kind {
+            "Kustomization" => spec(value).map(FluxObject::Kustomization),
+            "HelmRelease" => spec(value).map(|s| FluxObject::HelmRelease(Box::new(s))),
+            "GitRepository" => spec(value).map(FluxObject::GitRepository),
+            "HelmRepository" => spec(value).map(FluxObject::HelmRepository),
+            "OCIRepository" => spec(value).map(FluxObject::OCIRepository),
+            "Bucket" => spec(value).map(FluxObject::Bucket),
+            "ImageRepository" => spec(value).map(FluxObject::ImageRepository),
+            "ImagePolicy" => spec(value).map(FluxObject::ImagePolicy),
+            "ImageUpdateAutomation" => spec(value).map(FluxObject::ImageUpdateAutomation),
+            "Alert" => spec(value).map(FluxObject::Alert),
+            "Provider" => spec(value).map(FluxObject::Provider),
+            "Receiver" => spec(value).map(FluxObject::Receiver),
+            _ => unreachable!("Every kind of FLUX_KINDS is read"),
+        };
+        object
+            .map(Some)
+            .wrap_err_with(|| format!("invalid {kind} spec"))
+    }
+
+    /// The kind of the object, e.g. `Kustomization`.
+    pub fn get_kind(&self) -> &'static str {
+        match self {
+            FluxObject::Kustomization(_) => "Kustomization",
+            FluxObject::HelmRelease(_) => "HelmRelease",
+            FluxObject::GitRepository(_) => "GitRepository",
+            FluxObject::HelmRepository(_) => "HelmRepository",
+            FluxObject::OCIRepository(_) => "OCIRepository",
+            FluxObject::Bucket(_) => "Bucket",
+            FluxObject::ImageRepository(_) => "ImageRepository",
+            FluxObject::ImagePolicy(_) => "ImagePolicy",
+            FluxObject::ImageUpdateAutomation(_) => "ImageUpdateAutomation",
+            FluxObject::Alert(_) => "Alert",
+            FluxObject::Provider(_) => "Provider",
+            FluxObject::Receiver(_) => "Receiver",
+        }
+    }
+
+    /// The sources the object is built from, along with the field referencing them,
+    /// e.g. the `spec.sourceRef` of a `Kustomization`.
+    pub fn get_source_refs(&self) -> Vec<(&'static str, &CrossNamespaceObjectReference)> {
+        match self {
+            FluxObject::Kustomization(s) => vec![("spec.sourceRef", &s.source_ref)],
+            FluxObject::HelmRelease(s) => {
+                let chart = s
+                    .chart
+                    .iter()
+                    .map(|c| ("spec.chart.spec.sourceRef", &c.spec.source_ref));
+                let chart_ref = s.chart_ref.iter().map(|r| ("spec.chartRef", r));
+                chart.chain(chart_ref).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The objects of the same kind the object is applied after, empty when it can't depend on any.
+    pub fn get_depends_on(&self) -> &[NamespacedObjectReference] {
+        match self {
+            FluxObject::Kustomization(s) => &s.depends_on,
+            FluxObject::HelmRelease(s) => &s.depends_on,
+            _ => &[],
+        }
+    }
+
+    /// Whether reconciling the object is suspended.
+    pub fn is_suspended(&self) -> bool {
+        match self {
+            FluxObject::Kustomization(s) => s.suspend,
+            FluxObject::HelmRelease(s) => s.suspend,
+            FluxObject::GitRepository(s) => s.suspend,
+            FluxObject::HelmRepository(s) => s.suspend,
+            FluxObject::OCIRepository(s) => s.suspend,
+            FluxObject::Bucket(s) => s.suspend,
+            FluxObject::ImageRepository(s) => s.suspend,
+            // Policies are not reconciled on their own
+            FluxObject::ImagePolicy(_) => false,
+            FluxObject::ImageUpdateAutomation(s) => s.suspend,
+            FluxObject::Alert(s) => s.suspend,
+            FluxObject::Provider(s) => s.suspend,
+            FluxObject::Receiver(s) => s.suspend,
+        }
+    }
+}
+
+impl LocalObjectReference {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl NamespacedObjectReference {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+}
+
+impl CrossNamespaceObjectReference {
+    pub fn get_api_version(&self) -> Option<&str> {
+        self.api_version.as_deref()
+    }
+
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// The namespace of the object, the one of the referencing object when there is none.
+    pub fn get_namespace_or<'a>(&'a self, default: Option<&'a str>) -> Option<&'a str> {
+        self.namespace.as_deref().or(default)
+    }
+
+    /// Whether the object is the one referenced, `namespace` is the one of the referencing
+    /// object. A namespace that is not known, e.g. set by a kustomize overlay, matches any
+    /// namespace.
+    pub fn matches(&self, id: &ResourceId, namespace: Option<&str>) -> bool {
+        let namespace_matches = match (self.get_namespace_or(namespace), id.get_namespace()) {
+            (Some(referenced), Some(defined)) => referenced == defined,
+            _ => true,
+        };
+        let group_matches = match self.api_version.as_deref().and_then(|v| v.split_once('/')) {
+            Some((group, _)) => group == id.get_group(),
+            None => true,
+        };
+        self.kind == id.get_kind()
+            && self.name == id.get_name()
+            && namespace_matches
+            && group_matches
+    }
+}
+
+impl Decryption {
+    pub fn get_provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+}
+
+impl KustomizationSpec {
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_source_ref(&self) -> &CrossNamespaceObjectReference {
+        &self.source_ref
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_depends_on(&self) -> &[NamespacedObjectReference] {
+        &self.depends_on
+    }
+
+    pub fn is_prune(&self) -> bool {
+        self.prune
+    }
+
+    pub fn get_target_namespace(&self) -> Option<&str> {
+        self.target_namespace.as_deref()
+    }
+
+    pub fn get_service_account_name(&self) -> Option<&str> {
+        self.service_account_name.as_deref()
+    }
+
+    pub fn get_decryption(&self) -> Option<&Decryption> {
+        self.decryption.as_ref()
+    }
+}
+
+impl HelmChartTemplate {
+    pub fn get_spec(&self) -> &HelmChartTemplateSpec {
+        &self.spec
+    }
+}
+
+impl HelmChartTemplateSpec {
+    pub fn get_chart(&self) -> &str {
+        &self.chart
+    }
+
+    pub fn get_version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub fn get_source_ref(&self) -> &CrossNamespaceObjectReference {
+        &self.source_ref
+    }
+
+    pub fn get_values_files(&self) -> &[String] {
+        &self.values_files
+    }
+}
+
+impl ValuesReference {
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_values_key(&self) -> Option<&str> {
+        self.values_key.as_deref()
+    }
+
+    pub fn get_target_path(&self) -> Option<&str> {
+        self.target_path.as_deref()
+    }
+
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+}
+
+impl HelmReleaseSpec {
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_chart(&self) -> Option<&HelmChartTemplate> {
+        self.chart.as_ref()
+    }
+
+    pub fn get_chart_ref(&self) -> Option<&CrossNamespaceObjectReference> {
+        self.chart_ref.as_ref()
+    }
+
+    pub fn get_release_name(&self) -> Option<&str> {
+        self.release_name.as_deref()
+    }
+
+    pub fn get_target_namespace(&self) -> Option<&str> {
+        self.target_namespace.as_deref()
+    }
+
+    pub fn get_storage_namespace(&self) -> Option<&str> {
+        self.storage_namespace.as_deref()
+    }
+
+    pub fn get_depends_on(&self) -> &[NamespacedObjectReference] {
+        &self.depends_on
+    }
+
+    pub fn get_service_account_name(&self) -> Option<&str> {
+        self.service_account_name.as_deref()
+    }
+
+    pub fn get_values(&self) -> Option<&Value> {
+        self.values.as_ref()
+    }
+
+    pub fn get_values_from(&self) -> &[ValuesReference] {
+        &self.values_from
+    }
+}
+
+impl GitRepositoryRef {
+    pub fn get_branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
+    pub fn get_tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub fn get_semver(&self) -> Option<&str> {
+        self.semver.as_deref()
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn get_commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+}
+
+impl GitRepositorySpec {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_ref(&self) -> Option<&GitRepositoryRef> {
+        self.reference.as_ref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+}
+
+impl HelmRepositorySpec {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_interval(&self) -> Option<&str> {
+        self.interval.as_deref()
+    }
+
+    pub fn get_type(&self) -> Option<&str> {
+        self.repository_type.as_deref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+}
+
+impl OCIRepositoryRef {
+    pub fn get_digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    pub fn get_semver(&self) -> Option<&str> {
+        self.semver.as_deref()
+    }
+
+    pub fn get_tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+}
+
+impl OCIRepositorySpec {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_ref(&self) -> Option<&OCIRepositoryRef> {
+        self.reference.as_ref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+}
+
+impl BucketSpec {
+    pub fn get_bucket_name(&self) -> &str {
+        &self.bucket_name
+    }
+
+    pub fn get_endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+
+    pub fn get_region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    pub fn get_prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+}
+
+impl ImageRepositorySpec {
+    pub fn get_image(&self) -> &str {
+        &self.image
+    }
+
+    pub fn get_interval(&self) -> Option<&str> {
+        self.interval.as_deref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+
+    pub fn get_exclusion_list(&self) -> &[String] {
+        &self.exclusion_list
+    }
+}
+
+impl ImagePolicyChoice {
+    pub fn get_semver(&self) -> Option<&SemVerPolicy> {
+        self.semver.as_ref()
+    }
+
+    pub fn get_alphabetical(&self) -> Option<&OrderPolicy> {
+        self.alphabetical.as_ref()
+    }
+
+    pub fn get_numerical(&self) -> Option<&OrderPolicy> {
+        self.numerical.as_ref()
+    }
+}
+
+impl SemVerPolicy {
+    pub fn get_range(&self) -> &str {
+        &self.range
+    }
+}
+
+impl OrderPolicy {
+    pub fn get_order(&self) -> Option<&str> {
+        self.order.as_deref()
+    }
+}
+
+impl TagFilter {
+    pub fn get_pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn get_extract(&self) -> Option<&str> {
+        self.extract.as_deref()
+    }
+}
+
+impl ImagePolicySpec {
+    pub fn get_image_repository_ref(&self) -> &NamespacedObjectReference {
+        &self.image_repository_ref
+    }
+
+    pub fn get_policy(&self) -> &ImagePolicyChoice {
+        &self.policy
+    }
+
+    pub fn get_filter_tags(&self) -> Option<&TagFilter> {
+        self.filter_tags.as_ref()
+    }
+}
+
+impl UpdateStrategy {
+    pub fn get_strategy(&self) -> &str {
+        &self.strategy
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+}
+
+impl ImageUpdateAutomationSpec {
+    /// The `GitRepository` to update, with its kind filled in.
+    pub fn get_source_ref(&self) -> CrossNamespaceObjectReference {
+        self.source_ref.clone().into()
+    }
+
+    pub fn get_interval(&self) -> &str {
+        &self.interval
+    }
+
+    pub fn get_git(&self) -> Option<&Value> {
+        self.git.as_ref()
+    }
+
+    pub fn get_update(&self) -> Option<&UpdateStrategy> {
+        self.update.as_ref()
+    }
+}
+
+impl AlertSpec {
+    pub fn get_provider_ref(&self) -> &LocalObjectReference {
+        &self.provider_ref
+    }
+
+    pub fn get_event_severity(&self) -> Option<&str> {
+        self.event_severity.as_deref()
+    }
+
+    pub fn get_event_sources(&self) -> &[CrossNamespaceObjectReference] {
+        &self.event_sources
+    }
+
+    pub fn get_summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+}
+
+impl ProviderSpec {
+    pub fn get_type(&self) -> &str {
+        &self.provider_type
+    }
+
+    pub fn get_channel(&self) -> Option<&str> {
+        self.channel.as_deref()
+    }
+
+    pub fn get_address(&self) -> Option<&str> {
+        self.address.as_deref()
+    }
+
+    pub fn get_secret_ref(&self) -> Option<&LocalObjectReference> {
+        self.secret_ref.as_ref()
+    }
+}
+
+impl ReceiverSpec {
+    pub fn get_type(&self) -> &str {
+        &self.receiver_type
+    }
+
+    pub fn get_events(&self) -> &[String] {
+        &self.events
+    }
+
+    pub fn get_resources(&self) -> &[CrossNamespaceObjectReference] {
+        &self.resources
+    }
+
+    pub fn get_secret_ref(&self) -> &LocalObjectReference {
+        &self.secret_ref
+    }
+}
@@ -0,0 +1,388 @@
+use super::*;
+
+/// An item of the `dependsOn` of a Flux object.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    /// The object depended on, in the namespace of the dependent object when the item has none
+    target: ResourceId,
+    /// Where the item is
+    position: Option<Position>,
+    /// The objects matching the target, empty when it is not defined
+    resolved: Vec<ResourceId>,
+}
+
+/// A Flux object that can depend on others, a `Kustomization` or a `HelmRelease`.
+#[derive(Debug, Clone)]
+pub struct DependencyNode {
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0
+    index: usize,
+    dependencies: Vec<Dependency>,
+}
+
+/// The `dependsOn` graph of the Flux objects found by a scan.
+/// The objects depend on objects of their own kind, a `Kustomization` on other `Kustomization`s.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    nodes: BTreeMap<ResourceId, DependencyNode>,
+}
+
+/// The order the objects are applied in by Flux.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApplyOrder {
+    /// The objects of a stage only depend on objects of the stages before it
+    stages: Vec<Vec<ResourceId>>,
+    /// The objects never applied, their dependencies are missing or in a cycle
+    blocked: Vec<ResourceId>,
+}
+
+/// Whether the namespaces can be the same, a namespace that is not known matches any namespace.
+fn same_namespace(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => true,
+    }
+}
+
+impl DependencyGraph {
+    pub fn new(scan: &Scan) -> Self {
+        let mut nodes = BTreeMap::new();
+        for d in &scan.documents {
+            let depends_on = match d.get_flux() {
+                Some(flux @ (FluxObject::Kustomization(_) | FluxObject::HelmRelease(_))) => {
+                    flux.get_depends_on()
+                }
+                _ => continue,
+            };
+            let id = d.document.get_id();
+            let dependencies = depends_on
+                .iter()
+                .enumerate()
+                .map(|(i, r)| Dependency {
+                    target: ResourceId {
+                        group: id.group.clone(),
+                        kind: id.kind.clone(),
+                        namespace: r.get_namespace().or(id.get_namespace()).map(String::from),
+                        name: r.get_name().to_string(),
+                    },
+                    position: d
+                        .get_field_position(&format!("spec.dependsOn[{i}].name"))
+                        .or_else(|| d.get_field_position("spec.dependsOn")),
+                    resolved: vec![],
+                })
+                .collect();
+            // Duplicates are reported on their own, the first definition is kept
+            nodes.entry(id).or_insert(DependencyNode {
+                path: d.path.clone(),
+                index: d.index,
+                dependencies,
+            });
+        }
+
+        let ids: Vec<ResourceId> = nodes.keys().cloned().collect();
+        for node in nodes.values_mut() {
+            for dependency in &mut node.dependencies {
+                let target = &dependency.target;
+                dependency.resolved = ids
+                    .iter()
+                    .filter(|id| {
+                        id.group == target.group
+                            && id.kind == target.kind
+                            && id.name == target.name
+                            && same_namespace(id.get_namespace(), target.get_namespace())
+                    })
+                    .cloned()
+                    .collect();
+            }
+        }
+        DependencyGraph { nodes }
+    }
+
+    pub fn get_nodes(&self) -> &BTreeMap<ResourceId, DependencyNode> {
+        &self.nodes
+    }
+
+    /// The objects depended on by `id`.
+    fn successors<'a>(&'a self, id: &ResourceId) -> impl Iterator<Item = &'a ResourceId> {
+        self.nodes
+            .get(id)
+            .into_iter()
+            .flat_map(|n| n.dependencies.iter().flat_map(|d| d.resolved.iter()))
+    }
+
+    /// The groups of objects that depend on each other, the strongly connected components of
+    /// the graph with more than one object or depending on themselves.
+    fn cyclic_components(&self) -> Vec<BTreeSet<ResourceId>> {
+        // Tarjan's algorithm
+        struct State<'a> {
+            graph: &'a DependencyGraph,
+            index: HashMap<&'a ResourceId, usize>,
+            low: HashMap<&'a ResourceId, usize>,
+            stack: Vec<&'a ResourceId>,
+            on_stack: BTreeSet<&'a ResourceId>,
+            components: Vec<BTreeSet<ResourceId>>,
+        }
+
+        fn visit<'a>(state: &mut State<'a>, id: &'a ResourceId) {
+            let index = state.index.len();
+            state.index.insert(id, index);
+            state.low.insert(id, index);
+            state.stack.push(id);
+            state.on_stack.insert(id);
+            for next in state.graph.successors(id) {
+                if !state.index.contains_key(next) {
+                    visit(state, next);
+                    let low = state.low[id].min(state.low[next]);
+                    state.low.insert(id, low);
+                } else if state.on_stack.contains(next) {
+                    let low = state.low[id].min(state.index[next]);
+                    state.low.insert(id, low);
+                }
+            }
+            if state.low[id] == state.index[id] {
+                let mut component = BTreeSet::new();
+                while let Some(member) = state.stack.pop() {
+                    state.on_stack.remove(member);
+                    component.insert(member.clone());
+                    if member == id {
+                        break;
+                    }
+                }
+                let depends_on_itself = state.graph.successors(id).any(|next| next == id);
+                if component.len() > 1 || depends_on_itself {
+                    state.components.push(component);
+                }
+            }
+        }
+
+        let mut state = State {
+            graph: self,
+            index: HashMap::new(),
+            low: HashMap::new(),
+            stack: vec![],
+            on_stack: BTreeSet::new(),
+            components: vec![],
+        };
+        for id in self.nodes.keys() {
+            if !state.index.contains_key(id) {
+                visit(&mut state, id);
+            }
+        }
+        let mut components = state.components;
+        components.sort();
+        components
+    }
+
+    /// The cycles of the graph, one for every group of objects depending on each other.
+    /// Every cycle starts and ends with its first object, e.g. `[a, b, a]`.
+    pub fn get_cycles(&self) -> Vec<Vec<ResourceId>> {
+        self.cyclic_components()
+            .into_iter()
+            .filter_map(|component| {
+                // The shortest way back to the first object, within the component
+                let start = component.iter().next()?;
+                let mut previous: BTreeMap<&ResourceId, &ResourceId> = BTreeMap::new();
+                let mut queue = std::collections::VecDeque::from([start]);
+                while let Some(id) = queue.pop_front() {
+                    for next in self.successors(id).filter(|n| component.contains(*n)) {
+                        if next == start {
+                            let mut cycle = vec![start.clone(), id.clone()];
+                            let mut current = id;
+                            while let Some(p) = previous.get(current) {
+                                cycle.push((*p).clone());
+                                current = p;
+                            }
+                            cycle.reverse();
+                            return Some(cycle);
+                        }
+                        if next != start && !previous.contains_key(next) {
+                            previous.insert(next, id);
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                None
+            })
+            .collect()
+    }
+
+    /// The order Flux applies the objects in. An object is applied once all of its
+    /// dependencies are, the ones with missing dependencies or in a cycle never are.
+    pub fn get_apply_order(&self) -> ApplyOrder {
+        let mut applied: BTreeSet<&ResourceId> = BTreeSet::new();
+        let mut stages = vec![];
+        loop {
+            let stage: Vec<&ResourceId> = self
+                .nodes
+                .iter()
+                .filter(|(id, node)| {
+                    !applied.contains(id)
+                        && node.dependencies.iter().all(|d| {
+                            !d.resolved.is_empty() && d.resolved.iter().all(|r| applied.contains(r))
+                        })
+                })
+                .map(|(id, _)| id)
+                .collect();
+            if stage.is_empty() {
+                break;
+            }
+            applied.extend(stage.iter().copied());
+            stages.push(stage.into_iter().cloned().collect());
+        }
+        ApplyOrder {
+            stages,
+            blocked: self
+                .nodes
+                .keys()
+                .filter(|id| !applied.contains(id))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl DependencyNode {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_dependencies(&self) -> &[Dependency] {
+        &self.dependencies
+    }
+
+    /// The dependency on `id`, if there is one.
+    pub fn get_dependency(&self, id: &ResourceId) -> Option<&Dependency> {
+        self.dependencies.iter().find(|d| d.resolved.contains(id))
+    }
+}
+
+impl Dependency {
+    pub fn get_target(&self) -> &ResourceId {
+        &self.target
+    }
+
+    pub fn get_position(&self) -> Option<Position> {
+        self.position
+    }
+
+    pub fn get_resolved(&self) -> &[ResourceId] {
+        &self.resolved
+    }
+
+    /// The object depended on is not defined.
+    pub fn is_missing(&self) -> bool {
+        self.resolved.is_empty()
+    }
+}
+
+impl ApplyOrder {
+    pub fn get_stages(&self) -> &[Vec<ResourceId>] {
+        &self.stages
+    }
+
+    pub fn get_blocked(&self) -> &[ResourceId] {
+        &self.blocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> ResourceId {
+        ResourceId {
+            group: "kustomize.toolkit.fluxcd.io".to_string(),
+            kind: "Kustomization".to_string(),
+            namespace: Some("flux-system".to_string()),
+            name: name.to_string(),
+        }
+    }
+
+    /// A graph of `Kustomization`s, every object with the names of the objects it depends on.
+    fn graph(objects: &[(&str, &[&str])]) -> DependencyGraph {
+        let defined = |name: &str| objects.iter().any(|(n, _)| *n == name);
+        let nodes = objects
+            .iter()
+            .map(|(name, depends_on)| {
+                let dependencies = depends_on
+                    .iter()
+                    .map(|d| Dependency {
+                        target: id(d),
+                        position: None,
+                        resolved: if defined(d) { vec![id(d)] } else { vec![] },
+                    })
+                    .collect();
+                let node = DependencyNode {
+                    path: PathBuf::from("ks.yaml"),
+                    index: 0,
+                    dependencies,
+                };
+                (id(name), node)
+            })
+            .collect();
+        DependencyGraph { nodes }
+    }
+
+    fn ids(names: &[&str]) -> Vec<ResourceId> {
+        names.iter().map(|n| id(n)).collect()
+    }
+
+    #[test]
+    fn self_loop() {
+        let graph = graph(&[("selfish", &["selfish"]), ("infra", &[])]);
+        assert_eq!(graph.get_cycles(), [ids(&["selfish", "selfish"])]);
+        assert_eq!(graph.get_apply_order().get_blocked(), ids(&["selfish"]));
+    }
+
+    #[test]
+    fn multi_node_cycle() {
+        let graph = graph(&[("a", &["c"]), ("b", &["a"]), ("c", &["b"])]);
+        assert_eq!(graph.get_cycles(), [ids(&["a", "c", "b", "a"])]);
+    }
+
+    #[test]
+    fn shortest_cycle_of_a_component() {
+        // a -> b -> c -> a and b -> a are a single group, reported by its shortest cycle from a
+        let graph = graph(&[("a", &["b"]), ("b", &["c", "a"]), ("c", &["a"])]);
+        assert_eq!(graph.get_cycles(), [ids(&["a", "b", "a"])]);
+    }
+
+    #[test]
+    fn depending_on_a_cycle_is_not_a_cycle() {
+        let graph = graph(&[
+            ("a", &["b"]),
+            ("b", &["a"]),
+            ("apps", &["a"]),
+            ("selfish", &["selfish"]),
+        ]);
+        assert_eq!(
+            graph.get_cycles(),
+            [ids(&["a", "b", "a"]), ids(&["selfish", "selfish"])]
+        );
+        assert_eq!(
+            graph.get_apply_order().get_blocked(),
+            ids(&["a", "apps", "b", "selfish"])
+        );
+    }
+
+    #[test]
+    fn apply_order() {
+        let graph = graph(&[
+            ("apps", &["infra"]),
+            ("infra", &[]),
+            ("lost", &["nothere"]),
+            ("monitoring", &["apps", "infra"]),
+        ]);
+        assert!(graph.get_cycles().is_empty());
+        let order = graph.get_apply_order();
+        assert_eq!(
+            order.get_stages(),
+            [ids(&["infra"]), ids(&["apps"]), ids(&["monitoring"])]
+        );
+        assert_eq!(order.get_blocked(), ids(&["lost"]));
+    }
+}
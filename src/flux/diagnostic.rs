@@ -0,0 +1,101 @@
+use super::*;
+use codespan_reporting::{
+    diagnostic::{Diagnostic, Label, Severity as DiagnosticSeverity},
+    files::SimpleFiles,
+    term::{self, termcolor::WriteColor},
+};
+use std::ops::Range;
+
+type Files = SimpleFiles<String, String>;
+
+fn diagnostic_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::Error,
+        Severity::Warning => DiagnosticSeverity::Warning,
+        Severity::Info => DiagnosticSeverity::Note,
+    }
+}
+
+/// The bytes from the position to the end of its line, e.g. `name: podinfo`.
+/// Positions count chars, codespan wants bytes.
+fn line_range(content: &str, position: Position) -> Range<usize> {
+    let mut start = 0;
+    for line in content.split_inclusive('\n').take(position.line - 1) {
+        start += line.len();
+    }
+    let line = content[start..].lines().next().unwrap_or_default();
+    let column = line
+        .char_indices()
+        .nth(position.column - 1)
+        .map_or(line.len(), |(i, _)| i);
+    start + column..start + line.trim_end().len().max(column)
+}
+
+/// The labels of a finding, the first location is the primary one.
+/// The locations that can't be shown are notes.
+fn labels(
+    root: Option<&Path>,
+    finding: &Finding,
+    files: &mut Files,
+    ids: &mut HashMap<PathBuf, Option<usize>>,
+) -> (Vec<Label<usize>>, Vec<String>) {
+    let (primary, secondary) = match finding.get_rule() {
+        Rule::DuplicateResource => ("defined here", "also defined here"),
+        Rule::UnencryptedSecret => ("no `sops` block", ""),
+        Rule::ParseError => ("", ""),
+        Rule::DisallowedKey => ("encrypted here", ""),
+        Rule::UnusedSuppression => ("ignore comment", ""),
+        Rule::DanglingSourceRef => ("referenced here", ""),
+        Rule::MissingDependency => ("depended on here", ""),
+        Rule::DependencyCycle => (
+            "depends on the next object here",
+            "then on the next one here",
+        ),
+    };
+    let mut labels = vec![];
+    let mut notes = vec![];
+    for (i, l) in finding.get_locations().iter().enumerate() {
+        let id = *ids.entry(l.get_path().clone()).or_insert_with(|| {
+            let path = match root {
+                Some(root) => root.join(l.get_path()),
+                None => l.get_path().clone(),
+            };
+            let content = std::fs::read_to_string(path).ok()?;
+            Some(files.add(l.get_path().display().to_string(), content))
+        });
+        // The file may be gone since it was scanned
+        let (id, position) = match (id, l.get_position()) {
+            (Some(id), Some(position)) => (id, position),
+            _ => {
+                notes.push(format!("in {l}"));
+                continue;
+            }
+        };
+        let range = line_range(files.get(id).unwrap().source(), position);
+        labels.push(match i {
+            0 => Label::primary(id, range).with_message(primary),
+            _ => Label::secondary(id, range).with_message(secondary),
+        });
+    }
+    (labels, notes)
+}
+
+impl Report {
+    /// Writes the findings as compiler style diagnostics, with the YAML they are about.
+    /// The files are read again to show the YAML.
+    pub fn write_diagnostics(&self, writer: &mut dyn WriteColor) -> Result<()> {
+        let config = term::Config::default();
+        let mut files = Files::new();
+        let mut ids = HashMap::new();
+        for finding in self.get_findings() {
+            let (labels, notes) = labels(self.get_root(), finding, &mut files, &mut ids);
+            let diagnostic = Diagnostic::new(diagnostic_severity(finding.get_severity()))
+                .with_code(finding.get_rule().get_id())
+                .with_message(finding.get_message())
+                .with_labels(labels)
+                .with_notes(notes);
+            term::emit(writer, &config, &files, &diagnostic)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use super::*;
+use std::fmt::Write;
+
+/// Escapes text for XML attributes and text nodes.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A test case, failed when `failure` has the message and the details.
+struct TestCase {
+    name: String,
+    failure: Option<(String, String)>,
+}
+
+fn write_suite(xml: &mut String, name: &str, cases: &[TestCase]) -> fmt::Result {
+    let failures = cases.iter().filter(|c| c.failure.is_some()).count();
+    let name = escape(name);
+    writeln!(
+        xml,
+        r#"  <testsuite name="{name}" tests="{}" failures="{failures}">"#,
+        cases.len()
+    )?;
+    for case in cases {
+        let case_name = escape(&case.name);
+        match &case.failure {
+            None => writeln!(
+                xml,
+                r#"    <testcase classname="{name}" name="{case_name}"/>"#
+            )?,
+            Some((message, details)) => {
+                writeln!(
+                    xml,
+                    r#"    <testcase classname="{name}" name="{case_name}">"#
+                )?;
+                writeln!(
+                    xml,
+                    r#"      <failure type="{name}" message="{}">{}</failure>"#,
+                    escape(message),
+                    escape(details)
+                )?;
+                writeln!(xml, "    </testcase>")?;
+            }
+        }
+    }
+    writeln!(xml, "  </testsuite>")
+}
+
+/// A test case for every scanned file, failed by the findings of the rule in that file.
+fn rule_cases(report: &Report, rule: Rule) -> Vec<TestCase> {
+    report
+        .get_files()
+        .iter()
+        .map(|path| {
+            let findings: Vec<&Finding> = report
+                .get_findings()
+                .iter()
+                .filter(|f| {
+                    f.get_rule() == rule && f.get_locations().iter().any(|l| l.get_path() == path)
+                })
+                .collect();
+            let failure = match findings.as_slice() {
+                [] => None,
+                [finding] => Some((finding.get_message().to_string(), finding_details(finding))),
+                _ => Some((
+                    format!("{} {rule} findings", findings.len()),
+                    findings.iter().map(|f| finding_details(f)).collect(),
+                )),
+            };
+            TestCase {
+                name: path.to_string_lossy().to_string(),
+                failure,
+            }
+        })
+        .collect()
+}
+
+/// The message of the finding and every location, e.g. the conflicting files of a duplicate.
+fn finding_details(finding: &Finding) -> String {
+    let mut details = format!("{}\n", finding.get_message());
+    for l in finding.get_locations() {
+        details.push_str(&format!("  {l}\n"));
+    }
+    details
+}
+
+/// A test case for every rotated file, failed when the file failed to rotate.
+fn rotation_cases(rotation: &RotationReport) -> Vec<TestCase> {
+    rotation
+        .get_files()
+        .iter()
+        .map(|f| TestCase {
+            name: f.get_path().to_string_lossy().to_string(),
+            failure: f
+                .get_error()
+                .map(|e| ("failed to rotate".to_string(), e.to_string())),
+        })
+        .collect()
+}
+
+impl Report {
+    /// Turns the report into a JUnit XML report, for CI test dashboards.
+    /// Every rule is a test suite with a test case for every scanned file, rotations are a test
+    /// suite of their own.
+    pub fn to_junit(&self) -> String {
+        let mut suites: Vec<(&str, Vec<TestCase>)> = Rule::ALL
+            .iter()
+            .map(|rule| (rule.get_id(), rule_cases(self, *rule)))
+            .collect();
+        if let Some(rotation) = self.get_rotation() {
+            suites.push(("rotation", rotation_cases(rotation)));
+        }
+
+        let tests: usize = suites.iter().map(|(_, cases)| cases.len()).sum();
+        let failures = suites
+            .iter()
+            .flat_map(|(_, cases)| cases)
+            .filter(|c| c.failure.is_some())
+            .count();
+        let mut xml = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<testsuites name="flux-validator" tests="{tests}" failures="{failures}">"#
+        );
+        for (name, cases) in &suites {
+            let _ = write_suite(&mut xml, name, cases);
+        }
+        let _ = writeln!(xml, "</testsuites>");
+        xml
+    }
+}
@@ -0,0 +1,1087 @@
+use super::*;
+
+/// Bumped whenever a field is removed or changes meaning, adding fields keeps the version.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Everything found while validating a directory.
+/// Shared by every output format, serializes to the stable JSON schema.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    schema_version: u32,
+    /// The directory the paths are relative to, absent when they are not
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_root"
+    )]
+    root: Option<PathBuf>,
+    /// Every file scanned
+    #[serde(serialize_with = "serialize_paths")]
+    files: Vec<PathBuf>,
+    /// Resources defined more than once, only the ones reported by a `duplicate-resource` finding
+    duplicates: Vec<DuplicateGroup>,
+    /// The keys used by sops and the files encrypted with them
+    keys: Vec<KeyUsage>,
+    /// The order the `Kustomization`s and `HelmRelease`s are applied in, following `dependsOn`
+    apply_order: ApplyOrder,
+    /// What was skipped while scanning
+    warnings: Vec<Warning>,
+    /// Everything that should be fixed, from all the rules
+    findings: Vec<Finding>,
+    /// The findings ignored by a comment
+    suppressed: Vec<SuppressedFinding>,
+    /// The findings already in the baseline, they are not reported
+    baselined: Vec<Finding>,
+    /// Absent when no rotation was asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rotation: Option<RotationReport>,
+}
+
+/// The checks findings come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// The same resource is defined more than once
+    DuplicateResource,
+    /// A `Secret` is not encrypted by sops
+    UnencryptedSecret,
+    /// A file or a document could not be parsed
+    ParseError,
+    /// A sops file is encrypted with a key that is not in the `allowed-keys` of the config
+    DisallowedKey,
+    /// An ignore comment does not ignore anything
+    UnusedSuppression,
+    /// A `sourceRef` names a source that is not defined in the repo
+    DanglingSourceRef,
+    /// A `dependsOn` names an object that is not defined in the repo
+    MissingDependency,
+    /// Objects depend on each other through `dependsOn`
+    DependencyCycle,
+}
+
+/// How bad a finding is, ordered from the least to the most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something wrong found by a rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    rule: Rule,
+    severity: Severity,
+    message: String,
+    /// The resource the finding is about, absent when it is about a file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource: Option<ResourceId>,
+    /// Where the finding is, the first location is the main one
+    locations: Vec<FindingLocation>,
+}
+
+/// A finding ignored by comments.
+#[derive(Debug, Clone, Serialize)]
+pub struct SuppressedFinding {
+    #[serde(flatten)]
+    finding: Finding,
+    /// The comments ignoring the finding
+    suppressed_by: Vec<Suppression>,
+}
+
+/// Where a finding is.
+#[derive(Debug, Clone, Serialize)]
+pub struct FindingLocation {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0, absent when it is the whole file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<Position>,
+}
+
+/// A resource and the definitions of it, when it is defined more than once.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    #[serde(flatten)]
+    id: ResourceId,
+    /// The definitions are not all encrypted with the same keys
+    encryption_differs: bool,
+    definitions: Vec<DefinitionReport>,
+}
+
+/// Where a resource is defined and what it is encrypted with.
+#[derive(Debug, Clone, Serialize)]
+pub struct DefinitionReport {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0
+    index: usize,
+    /// Where the `metadata.name` of the definition is
+    position: Position,
+    /// Empty when the definition is not encrypted
+    keys: Vec<String>,
+}
+
+/// A key and the files encrypted with it.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyUsage {
+    key: String,
+    #[serde(serialize_with = "serialize_paths")]
+    files: Vec<PathBuf>,
+}
+
+/// The files rotated, or planned to be, to a new key.
+#[derive(Debug, Clone, Serialize)]
+pub struct RotationReport {
+    key: String,
+    /// Nothing was rotated, the files are only planned
+    dry_run: bool,
+    files: Vec<RotatedFile>,
+    /// The keys used by the rotated files after the rotation, absent on dry runs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keys_after: Option<Vec<KeyUsage>>,
+}
+
+/// A file in a `RotationReport`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RotatedFile {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    /// The keys before the rotation
+    from: Vec<String>,
+    /// The keys after the rotation, or the keys planned on dry runs
+    to: Vec<String>,
+    status: RotatedFileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotatedFileStatus {
+    Planned,
+    Rotated,
+    /// The file was left unchanged
+    Failed,
+}
+
+fn serialize_root<S: serde::Serializer>(
+    root: &Option<PathBuf>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match root {
+        Some(root) => serialize_path(root, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Turns the result of `get_kms_keys` into a list.
+fn key_usages(keys_used: &BTreeMap<String, BTreeSet<PathBuf>>) -> Vec<KeyUsage> {
+    keys_used
+        .iter()
+        .map(|(key, files)| KeyUsage {
+            key: key.clone(),
+            files: files.iter().cloned().collect(),
+        })
+        .collect()
+}
+
+impl Report {
+    /// Builds the report of a scan, from the result of `get_kms_keys` and `get_dup_documents`.
+    /// The findings ignored by a comment are moved to `suppressed`.
+    pub fn new(
+        scan: &Scan,
+        keys_used: &BTreeMap<String, BTreeSet<PathBuf>>,
+        documents: &BTreeMap<ResourceId, Vec<Definition>>,
+    ) -> Self {
+        let mut report = Report::unsuppressed(scan, keys_used, documents);
+        report.suppress(scan.get_suppressions(), |rule| rule != Rule::DisallowedKey);
+        report
+    }
+
+    /// Builds the report with every finding, ignore comments or not.
+    fn unsuppressed(
+        scan: &Scan,
+        keys_used: &BTreeMap<String, BTreeSet<PathBuf>>,
+        documents: &BTreeMap<ResourceId, Vec<Definition>>,
+    ) -> Self {
+        let duplicates = documents
+            .iter()
+            .filter(|(_, definitions)| definitions.len() > 1)
+            .map(|(id, definitions)| DuplicateGroup {
+                id: id.clone(),
+                encryption_differs: encryption_differs(definitions),
+                definitions: definitions
+                    .iter()
+                    .map(|d| DefinitionReport {
+                        path: d.path.clone(),
+                        index: d.index,
+                        position: d.position,
+                        keys: d.sops.as_ref().map(Sops::get_key_ids).unwrap_or_default(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let graph = DependencyGraph::new(scan);
+        let findings = duplicate_findings(documents)
+            .chain(unencrypted_secret_findings(scan))
+            .chain(parse_error_findings(scan))
+            .chain(dangling_source_ref_findings(scan))
+            .chain(missing_dependency_findings(&graph))
+            .chain(dependency_cycle_findings(&graph))
+            .collect();
+        Report {
+            schema_version: REPORT_SCHEMA_VERSION,
+            root: None,
+            files: scan.files.clone(),
+            duplicates,
+            keys: key_usages(keys_used),
+            apply_order: graph.get_apply_order(),
+            warnings: scan.warnings.clone(),
+            findings,
+            suppressed: vec![],
+            baselined: vec![],
+            rotation: None,
+        }
+    }
+
+    /// Builds the report like `Report::new`, with the rules and severities of the config.
+    /// The `disallowed-key` rule only runs when the config has `allowed-keys`.
+    pub fn with_config(
+        scan: &Scan,
+        keys_used: &BTreeMap<String, BTreeSet<PathBuf>>,
+        documents: &BTreeMap<ResourceId, Vec<Definition>>,
+        config: &Config,
+    ) -> Result<Self> {
+        let mut report = Report::unsuppressed(scan, keys_used, documents);
+        if let Some(allowed) = config.get_allowed_keys() {
+            let allowed = allowed
+                .iter()
+                .map(|k| KeyMatcher::parse_key(k))
+                .collect::<Result<Vec<_>>>()?;
+            report
+                .findings
+                .extend(disallowed_key_findings(scan, &allowed));
+        }
+        let ran = |rule| {
+            config.is_enabled(rule)
+                && (rule != Rule::DisallowedKey || config.get_allowed_keys().is_some())
+        };
+        report.findings.retain(|f| ran(f.rule));
+        report.suppress(scan.get_suppressions(), ran);
+        let suppressed = report.suppressed.iter_mut().map(|s| &mut s.finding);
+        for f in report.findings.iter_mut().chain(suppressed) {
+            f.severity = config.get_severity(f.rule);
+        }
+        Ok(report)
+    }
+
+    /// Moves the findings ignored by a comment to `suppressed`, and reports the comments that
+    /// ignore nothing. The comments of the rules that did not `ran` are not reported.
+    fn suppress(&mut self, suppressions: &[Suppression], ran: impl Fn(Rule) -> bool) {
+        let mut used = vec![false; suppressions.len()];
+        let mut findings = vec![];
+        for finding in std::mem::take(&mut self.findings) {
+            let by: Vec<usize> = (0..suppressions.len())
+                .filter(|i| {
+                    let covers = |l| suppressions[*i].covers(finding.rule, l);
+                    finding.locations.iter().any(covers)
+                })
+                .collect();
+            if by.is_empty() {
+                findings.push(finding);
+                continue;
+            }
+            by.iter().for_each(|i| used[*i] = true);
+            self.suppressed.push(SuppressedFinding {
+                finding,
+                suppressed_by: by.iter().map(|i| suppressions[*i].clone()).collect(),
+            });
+        }
+        if ran(Rule::UnusedSuppression) {
+            let unused = suppressions
+                .iter()
+                .zip(used)
+                .filter(|(s, used)| !used && s.get_rule().is_none_or(&ran))
+                .map(|(s, _)| unused_suppression_finding(s));
+            findings.extend(unused);
+        }
+        self.findings = findings;
+        self.retain_reported_duplicates();
+    }
+
+    /// Only keeps the duplicates that are still reported, the ones ignored by a comment, in the
+    /// baseline or not checked are only in the findings they were moved to.
+    fn retain_reported_duplicates(&mut self) {
+        let reported: BTreeSet<&ResourceId> = self
+            .findings
+            .iter()
+            .filter(|f| f.rule == Rule::DuplicateResource)
+            .filter_map(|f| f.resource.as_ref())
+            .collect();
+        self.duplicates.retain(|d| reported.contains(&d.id));
+    }
+
+    /// A report with only a rotation, when a plan is applied without scanning.
+    pub fn from_rotation(rotation: RotationReport) -> Self {
+        Report {
+            schema_version: REPORT_SCHEMA_VERSION,
+            root: None,
+            files: vec![],
+            duplicates: vec![],
+            keys: vec![],
+            apply_order: ApplyOrder::default(),
+            warnings: vec![],
+            findings: vec![],
+            suppressed: vec![],
+            baselined: vec![],
+            rotation: Some(rotation),
+        }
+    }
+
+    pub fn set_rotation(&mut self, rotation: RotationReport) {
+        self.rotation = Some(rotation);
+    }
+
+    /// Moves the findings in the baseline to `baselined`, `root` is the directory that was scanned.
+    pub fn apply_baseline(&mut self, baseline: &Baseline, root: &Path) {
+        let (baselined, findings) = baseline.partition(std::mem::take(&mut self.findings), root);
+        self.findings = findings;
+        self.baselined = baselined;
+        self.retain_reported_duplicates();
+    }
+
+    /// Makes every path relative to `root`, the directory that was scanned.
+    /// The paths outside of `root` are kept as they are.
+    pub fn relative_to(&mut self, root: &Path) {
+        let strip = |path: &mut PathBuf| {
+            if let Ok(relative) = path.strip_prefix(root) {
+                *path = relative.to_path_buf();
+            }
+        };
+        let strip_keys = |keys: &mut Vec<KeyUsage>| {
+            keys.iter_mut()
+                .for_each(|k| k.files.iter_mut().for_each(strip))
+        };
+        self.files.iter_mut().for_each(strip);
+        for d in &mut self.duplicates {
+            d.definitions.iter_mut().for_each(|d| strip(&mut d.path));
+        }
+        strip_keys(&mut self.keys);
+        self.warnings
+            .iter_mut()
+            .for_each(|w| strip(w.get_path_mut()));
+        for f in &mut self.findings {
+            f.locations.iter_mut().for_each(|l| strip(&mut l.path));
+        }
+        for f in &mut self.baselined {
+            f.locations.iter_mut().for_each(|l| strip(&mut l.path));
+        }
+        for s in &mut self.suppressed {
+            s.finding
+                .locations
+                .iter_mut()
+                .for_each(|l| strip(&mut l.path));
+            s.suppressed_by
+                .iter_mut()
+                .for_each(|s| strip(s.get_path_mut()));
+        }
+        if let Some(rotation) = &mut self.rotation {
+            rotation.files.iter_mut().for_each(|f| strip(&mut f.path));
+            rotation.keys_after.iter_mut().for_each(strip_keys);
+        }
+        self.root = Some(root.to_path_buf());
+    }
+
+    /// The directory the paths are relative to, if they are.
+    pub fn get_root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    pub fn get_files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn get_duplicates(&self) -> &[DuplicateGroup] {
+        &self.duplicates
+    }
+
+    pub fn get_keys(&self) -> &[KeyUsage] {
+        &self.keys
+    }
+
+    pub fn get_apply_order(&self) -> &ApplyOrder {
+        &self.apply_order
+    }
+
+    pub fn get_warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn get_findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    pub fn get_suppressed(&self) -> &[SuppressedFinding] {
+        &self.suppressed
+    }
+
+    pub fn get_baselined(&self) -> &[Finding] {
+        &self.baselined
+    }
+
+    /// The severity of the worst finding, if there is any.
+    pub fn get_max_severity(&self) -> Option<Severity> {
+        self.findings.iter().map(|f| f.severity).max()
+    }
+
+    /// How many findings there are of the severity.
+    pub fn count_findings(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+
+    pub fn get_rotation(&self) -> Option<&RotationReport> {
+        self.rotation.as_ref()
+    }
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::DuplicateResource,
+        Rule::UnencryptedSecret,
+        Rule::ParseError,
+        Rule::DisallowedKey,
+        Rule::UnusedSuppression,
+        Rule::DanglingSourceRef,
+        Rule::MissingDependency,
+        Rule::DependencyCycle,
+    ];
+
+    /// The id of the rule, as serialized.
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            Rule::DuplicateResource => "duplicate-resource",
+            Rule::UnencryptedSecret => "unencrypted-secret",
+            Rule::ParseError => "parse-error",
+            Rule::DisallowedKey => "disallowed-key",
+            Rule::UnusedSuppression => "unused-suppression",
+            Rule::DanglingSourceRef => "dangling-source-ref",
+            Rule::MissingDependency => "missing-dependency",
+            Rule::DependencyCycle => "dependency-cycle",
+        }
+    }
+
+    /// The rule with this id, e.g. `duplicate-resource`.
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.get_id() == id)
+    }
+
+    /// How bad the findings of the rule are.
+    pub fn get_severity(&self) -> Severity {
+        match self {
+            Rule::DuplicateResource
+            | Rule::UnencryptedSecret
+            | Rule::DisallowedKey
+            | Rule::DanglingSourceRef
+            | Rule::MissingDependency
+            | Rule::DependencyCycle => Severity::Error,
+            // The rest of the repo is still validated
+            Rule::ParseError => Severity::Warning,
+            Rule::UnusedSuppression => Severity::Warning,
+        }
+    }
+
+    pub fn get_description(&self) -> &'static str {
+        match self {
+            Rule::DuplicateResource => {
+                "The same resource (api group, kind, namespace and name) is defined more than once"
+            }
+            Rule::UnencryptedSecret => "A Secret is not encrypted by sops",
+            Rule::ParseError => "A file or a document could not be parsed",
+            Rule::DisallowedKey => "A sops file is encrypted with a key that is not allowed",
+            Rule::UnusedSuppression => "An ignore comment does not ignore any finding",
+            Rule::DanglingSourceRef => {
+                "A Kustomization or a HelmRelease references a source that is not defined"
+            }
+            Rule::MissingDependency => "A dependsOn names an object that is not defined",
+            Rule::DependencyCycle => "Objects depend on each other through dependsOn",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_id())
+    }
+}
+
+impl Severity {
+    pub fn get_id(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_id())
+    }
+}
+
+impl Finding {
+    /// A finding with the severity of its rule.
+    fn new(
+        rule: Rule,
+        message: String,
+        resource: Option<ResourceId>,
+        locations: Vec<FindingLocation>,
+    ) -> Self {
+        Finding {
+            rule,
+            severity: rule.get_severity(),
+            message,
+            resource,
+            locations,
+        }
+    }
+
+    pub fn get_rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn get_resource(&self) -> Option<&ResourceId> {
+        self.resource.as_ref()
+    }
+
+    pub fn get_locations(&self) -> &[FindingLocation] {
+        &self.locations
+    }
+}
+
+impl FindingLocation {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn get_position(&self) -> Option<Position> {
+        self.position
+    }
+}
+
+impl fmt::Display for FindingLocation {
+    /// Formats as `path:line:column`, or with the document when the position is not known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        match (self.position, self.index) {
+            (Some(position), _) => write!(f, ":{position}"),
+            (None, Some(index)) => write!(f, " (document {})", document_number(index)),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn duplicate_findings(
+    documents: &BTreeMap<ResourceId, Vec<Definition>>,
+) -> impl Iterator<Item = Finding> + '_ {
+    documents
+        .iter()
+        .filter(|(_, definitions)| definitions.len() > 1)
+        .map(|(id, definitions)| {
+            let mut message = format!("{id} is defined {} times", definitions.len());
+            if encryption_differs(definitions) {
+                message.push_str(", and encrypted differently");
+            }
+            let locations = definitions
+                .iter()
+                .map(|d| FindingLocation {
+                    path: d.path.clone(),
+                    index: Some(d.index),
+                    position: Some(d.position),
+                })
+                .collect();
+            Finding::new(
+                Rule::DuplicateResource,
+                message,
+                Some(id.clone()),
+                locations,
+            )
+        })
+}
+
+fn unencrypted_secret_findings(scan: &Scan) -> impl Iterator<Item = Finding> + '_ {
+    scan.documents
+        .iter()
+        .filter(|d| {
+            let id = d.document.get_id();
+            id.get_kind() == "Secret" && id.get_group().is_empty() && !d.document.has_sops()
+        })
+        .map(|d| {
+            Finding::new(
+                Rule::UnencryptedSecret,
+                format!("{} is not encrypted", d.document.get_id()),
+                Some(d.document.get_id()),
+                vec![FindingLocation {
+                    path: d.path.clone(),
+                    index: Some(d.index),
+                    position: d.get_field_position("kind").or(Some(d.get_position())),
+                }],
+            )
+        })
+}
+
+fn parse_error_findings(scan: &Scan) -> impl Iterator<Item = Finding> + '_ {
+    scan.warnings.iter().filter_map(|w| {
+        let (index, position) = match w {
+            Warning::Invalid {
+                index, position, ..
+            }
+            | Warning::InvalidFlux {
+                index, position, ..
+            } => (Some(*index), Some(*position)),
+            Warning::ParseError { position, .. } => (None, *position),
+            // Skipping what is not a k8s object is expected
+            Warning::Empty { .. } | Warning::NotKubernetes { .. } => return None,
+        };
+        Some(Finding::new(
+            Rule::ParseError,
+            w.to_string(),
+            None,
+            vec![FindingLocation {
+                path: w.get_path().clone(),
+                index,
+                position,
+            }],
+        ))
+    })
+}
+
+/// The `sourceRef`s of the Flux objects that don't match any source found by the scan.
+fn dangling_source_ref_findings(scan: &Scan) -> Vec<Finding> {
+    let sources: Vec<ResourceId> = scan
+        .documents
+        .iter()
+        .map(|d| d.document.get_id())
+        .filter(|id| id.get_group() == SOURCE_GROUP)
+        .collect();
+    let mut findings = vec![];
+    for d in &scan.documents {
+        let refs = d.get_flux().map(FluxObject::get_source_refs);
+        let id = d.document.get_id();
+        for (field, source_ref) in refs.unwrap_or_default() {
+            if sources
+                .iter()
+                .any(|s| source_ref.matches(s, id.get_namespace()))
+            {
+                continue;
+            }
+            let (kind, name) = (source_ref.get_kind(), source_ref.get_name());
+            let referenced = match source_ref.get_namespace_or(id.get_namespace()) {
+                Some(namespace) => format!("{kind} {namespace}/{name}"),
+                None => format!("{kind} {name}"),
+            };
+            findings.push(Finding::new(
+                Rule::DanglingSourceRef,
+                format!("{id} references {referenced}, which is not defined"),
+                Some(id.clone()),
+                vec![FindingLocation {
+                    path: d.path.clone(),
+                    index: Some(d.index),
+                    position: d.get_field_position(field).or(Some(d.get_position())),
+                }],
+            ));
+        }
+    }
+    findings
+}
+
+/// `namespace/name`, or `name` when the namespace is not known.
+fn namespaced_name(id: &ResourceId) -> String {
+    match id.get_namespace() {
+        Some(namespace) => format!("{namespace}/{}", id.get_name()),
+        None => id.get_name().to_string(),
+    }
+}
+
+fn missing_dependency_findings(graph: &DependencyGraph) -> impl Iterator<Item = Finding> + '_ {
+    graph.get_nodes().iter().flat_map(|(id, node)| {
+        node.get_dependencies()
+            .iter()
+            .filter(|d| d.is_missing())
+            .map(move |d| {
+                Finding::new(
+                    Rule::MissingDependency,
+                    format!(
+                        "{id} depends on {}, which is not defined",
+                        namespaced_name(d.get_target())
+                    ),
+                    Some(id.clone()),
+                    vec![FindingLocation {
+                        path: node.get_path().clone(),
+                        index: Some(node.get_index()),
+                        position: d.get_position(),
+                    }],
+                )
+            })
+    })
+}
+
+/// A finding for every cycle, with the `dependsOn` item of every object of the cycle.
+fn dependency_cycle_findings(graph: &DependencyGraph) -> impl Iterator<Item = Finding> + '_ {
+    graph.get_cycles().into_iter().map(|cycle| {
+        let names: Vec<String> = cycle.iter().map(namespaced_name).collect();
+        let locations = cycle
+            .windows(2)
+            .map(|pair| {
+                let node = &graph.get_nodes()[&pair[0]];
+                FindingLocation {
+                    path: node.get_path().clone(),
+                    index: Some(node.get_index()),
+                    position: node
+                        .get_dependency(&pair[1])
+                        .and_then(Dependency::get_position),
+                }
+            })
+            .collect();
+        Finding::new(
+            Rule::DependencyCycle,
+            format!(
+                "{} objects depend on each other, {}",
+                cycle[0].get_kind(),
+                names.join(" -> ")
+            ),
+            Some(cycle[0].clone()),
+            locations,
+        )
+    })
+}
+
+fn disallowed_key_findings<'a>(
+    scan: &'a Scan,
+    allowed: &'a [KeyMatcher],
+) -> impl Iterator<Item = Finding> + 'a {
+    scan.documents.iter().filter_map(move |d| {
+        let disallowed: Vec<String> = d
+            .document
+            .get_sops()
+            .as_ref()?
+            .get_key_ids()
+            .into_iter()
+            .filter(|k| !allowed.iter().any(|m| m.matches(k)))
+            .collect();
+        if disallowed.is_empty() {
+            return None;
+        }
+        Some(Finding::new(
+            Rule::DisallowedKey,
+            format!(
+                "{} is encrypted with {}, which {} not allowed",
+                d.document.get_id(),
+                disallowed.join(", "),
+                if disallowed.len() == 1 { "is" } else { "are" }
+            ),
+            Some(d.document.get_id()),
+            vec![FindingLocation {
+                path: d.path.clone(),
+                index: Some(d.index),
+                position: d.get_field_position("sops").or(Some(d.get_position())),
+            }],
+        ))
+    })
+}
+
+fn unused_suppression_finding(suppression: &Suppression) -> Finding {
+    Finding::new(
+        Rule::UnusedSuppression,
+        suppression.unused_reason(),
+        None,
+        vec![FindingLocation {
+            path: suppression.get_path().clone(),
+            index: suppression.get_index(),
+            position: Some(suppression.get_position()),
+        }],
+    )
+}
+
+impl SuppressedFinding {
+    pub fn get_finding(&self) -> &Finding {
+        &self.finding
+    }
+
+    pub fn get_suppressed_by(&self) -> &[Suppression] {
+        &self.suppressed_by
+    }
+}
+
+impl DuplicateGroup {
+    pub fn get_id(&self) -> &ResourceId {
+        &self.id
+    }
+
+    pub fn encryption_differs(&self) -> bool {
+        self.encryption_differs
+    }
+
+    pub fn get_definitions(&self) -> &[DefinitionReport] {
+        &self.definitions
+    }
+}
+
+impl DefinitionReport {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn get_position(&self) -> Position {
+        self.position
+    }
+
+    pub fn get_keys(&self) -> &[String] {
+        &self.keys
+    }
+}
+
+impl KeyUsage {
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get_files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+impl RotationReport {
+    /// Reports the plan without rotating anything.
+    pub fn planned(plan: &RotationPlan) -> Self {
+        let files = plan
+            .files
+            .iter()
+            .map(|f| RotatedFile {
+                path: f.path.clone(),
+                from: f.from.clone(),
+                to: f.get_to(&plan.key),
+                status: RotatedFileStatus::Planned,
+                error: None,
+            })
+            .collect();
+        RotationReport {
+            key: plan.key.clone(),
+            dry_run: true,
+            files,
+            keys_after: None,
+        }
+    }
+
+    /// Reports the result of applying the plan, `keys_after` is the result of `get_kms_keys` on
+    /// the rotated files once the rotation is done.
+    pub fn applied(
+        plan: &RotationPlan,
+        rotations: &[Rotation],
+        keys_after: &BTreeMap<String, BTreeSet<PathBuf>>,
+    ) -> Self {
+        let before: HashMap<&PathBuf, &Vec<String>> =
+            plan.files.iter().map(|f| (&f.path, &f.from)).collect();
+        let after = get_keys_by_file(keys_after);
+        let files = rotations
+            .iter()
+            .map(|r| {
+                let (status, error) = match &r.status {
+                    RotationStatus::Rotated => (RotatedFileStatus::Rotated, None),
+                    RotationStatus::Failed(e) => (RotatedFileStatus::Failed, Some(e.clone())),
+                };
+                RotatedFile {
+                    path: r.path.clone(),
+                    from: before.get(&r.path).cloned().cloned().unwrap_or_default(),
+                    to: after.get(&r.path).cloned().unwrap_or_default(),
+                    status,
+                    error,
+                }
+            })
+            .collect();
+        RotationReport {
+            key: plan.key.clone(),
+            dry_run: false,
+            files,
+            keys_after: Some(key_usages(keys_after)),
+        }
+    }
+
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn get_files(&self) -> &[RotatedFile] {
+        &self.files
+    }
+
+    pub fn get_keys_after(&self) -> Option<&[KeyUsage]> {
+        self.keys_after.as_deref()
+    }
+
+    /// How many files failed to rotate.
+    pub fn failed_count(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.status == RotatedFileStatus::Failed)
+            .count()
+    }
+}
+
+impl RotatedFile {
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_from(&self) -> &[String] {
+        &self.from
+    }
+
+    pub fn get_to(&self) -> &[String] {
+        &self.to
+    }
+
+    pub fn get_status(&self) -> RotatedFileStatus {
+        self.status
+    }
+
+    pub fn get_error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "\
+apiVersion: v1
+kind: Secret
+metadata:
+  name: creds
+  namespace: apps
+data:
+  password: ENC[AES256_GCM,data:abc,type:str]
+sops:
+  kms:
+    - arn: arn:aws:kms:us-east-1:111:key/a
+      role: arn:aws:iam::111:role/r
+      enc: xx
+  age:
+    - recipient: age1keep
+      enc: yy
+  version: 3.7.3
+";
+
+    async fn disallowed(allowed_keys: &str) -> Vec<String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("creds.yaml");
+        std::fs::write(&path, SECRET).unwrap();
+        let scan = scan_documents(&vec![path]).await;
+        let keys_used = get_kms_keys(&scan).await.unwrap();
+        let config: Config = toml::from_str(&format!("allowed-keys = {allowed_keys}")).unwrap();
+        let report = Report::with_config(&scan, &keys_used, &BTreeMap::new(), &config).unwrap();
+        report
+            .get_findings()
+            .iter()
+            .filter(|f| f.get_rule() == Rule::DisallowedKey)
+            .map(|f| f.get_message().to_string())
+            .collect()
+    }
+
+    const DUPLICATE: &str = "\
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+---
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+";
+
+    async fn duplicates_report(content: &str, config: &Config) -> (Report, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.yaml");
+        std::fs::write(&path, content).unwrap();
+        let scan = scan_documents(&vec![path]).await;
+        let documents = get_dup_documents(&scan).await.unwrap();
+        let report = Report::with_config(&scan, &BTreeMap::new(), &documents, config).unwrap();
+        (report, dir)
+    }
+
+    #[tokio::test]
+    async fn duplicates_are_listed_while_reported() {
+        let (report, _) = duplicates_report(DUPLICATE, &Config::default()).await;
+        assert_eq!(report.get_duplicates().len(), 1);
+        assert_eq!(report.get_findings().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn suppressed_duplicates_are_not_listed() {
+        let content =
+            format!("# flux-validator: ignore duplicate-resource kept on purpose\n{DUPLICATE}");
+        let (report, _) = duplicates_report(&content, &Config::default()).await;
+        assert!(report.get_duplicates().is_empty());
+        assert_eq!(report.get_suppressed().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn disabled_duplicates_are_not_listed() {
+        let config: Config = toml::from_str("[rules.duplicate-resource]\nenabled = false").unwrap();
+        let (report, _) = duplicates_report(DUPLICATE, &config).await;
+        assert!(report.get_duplicates().is_empty());
+    }
+
+    #[tokio::test]
+    async fn baselined_duplicates_are_not_listed() {
+        let (mut report, dir) = duplicates_report(DUPLICATE, &Config::default()).await;
+        let baseline = Baseline::new(&report, dir.path());
+        report.apply_baseline(&baseline, dir.path());
+        assert!(report.get_duplicates().is_empty());
+        assert_eq!(report.get_baselined().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn allowed_arn_covers_any_role() {
+        let allowed = r#"["arn:aws:kms:us-east-1:111:key/a", "age1keep"]"#;
+        assert!(disallowed(allowed).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keys_not_allowed() {
+        assert_eq!(
+            disallowed(r#"["arn:aws:kms:us-east-1:111:key/b", "age1*"]"#).await,
+            ["Secret apps/creds is encrypted with \
+              arn:aws:kms:us-east-1:111:key/a+arn:aws:iam::111:role/r, which is not allowed"]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use super::*;
+use serde_json::{json, Value as Json};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// SARIF wants URIs, with `/` even on windows. Relative paths are kept relative.
+fn sarif_uri(path: &Path) -> String {
+    let uri = path.to_string_lossy().replace('\\', "/");
+    match path.is_absolute() {
+        true if uri.starts_with('/') => format!("file://{uri}"),
+        true => format!("file:///{uri}"),
+        false => uri,
+    }
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Info => "note",
+    }
+}
+
+fn sarif_location(location: &FindingLocation, message: Option<&str>) -> Json {
+    let mut physical = json!({
+        "artifactLocation": { "uri": sarif_uri(location.get_path()) },
+    });
+    if let Some(position) = location.get_position() {
+        physical["region"] = json!({
+            "startLine": position.line,
+            "startColumn": position.column,
+        });
+    }
+    let mut sarif = json!({ "physicalLocation": physical });
+    if let Some(message) = message {
+        sarif["message"] = json!({ "text": message });
+    }
+    sarif
+}
+
+/// A result for every location of the finding, so every file involved is flagged.
+/// The other locations are related locations of each result.
+fn sarif_results(finding: &Finding) -> impl Iterator<Item = Json> + '_ {
+    let related_message = match finding.get_rule() {
+        Rule::DependencyCycle => "also part of the cycle",
+        _ => "also defined here",
+    };
+    finding
+        .get_locations()
+        .iter()
+        .enumerate()
+        .map(move |(i, location)| {
+            let related: Vec<Json> = finding
+                .get_locations()
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(j, other)| {
+                    let mut related = sarif_location(other, Some(related_message));
+                    related["id"] = json!(j);
+                    related
+                })
+                .collect();
+            let mut result = json!({
+                "ruleId": finding.get_rule().get_id(),
+                "level": sarif_level(finding.get_severity()),
+                "message": { "text": finding.get_message() },
+                "locations": [sarif_location(location, None)],
+            });
+            if !related.is_empty() {
+                result["relatedLocations"] = Json::Array(related);
+            }
+            result
+        })
+}
+
+/// The results of a suppressed finding, marked as suppressed in the source by its comments.
+fn sarif_suppressed_results(suppressed: &SuppressedFinding) -> impl Iterator<Item = Json> + '_ {
+    let suppressions: Vec<Json> = suppressed
+        .get_suppressed_by()
+        .iter()
+        .map(|s| json!({ "kind": "inSource", "justification": s.get_reason() }))
+        .collect();
+    sarif_results(suppressed.get_finding()).map(move |mut result| {
+        result["suppressions"] = Json::Array(suppressions.clone());
+        result
+    })
+}
+
+impl Report {
+    /// Turns the findings into a SARIF 2.1.0 log, for code scanning tools.
+    /// Suppressed findings are kept with their suppressions, so the tools know they are ignored.
+    pub fn to_sarif(&self) -> Json {
+        let rules: Vec<Json> = Rule::ALL
+            .iter()
+            .map(|rule| {
+                json!({
+                    "id": rule.get_id(),
+                    "shortDescription": { "text": rule.get_description() },
+                    "defaultConfiguration": { "level": sarif_level(rule.get_severity()) },
+                })
+            })
+            .collect();
+        let results: Vec<Json> = self
+            .get_findings()
+            .iter()
+            .flat_map(sarif_results)
+            .chain(
+                self.get_suppressed()
+                    .iter()
+                    .flat_map(sarif_suppressed_results),
+            )
+            .collect();
+        json!({
+            "$schema": SARIF_SCHEMA,
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "flux-validator",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "results": results,
+            }],
+        })
+    }
+}
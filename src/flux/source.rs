@@ -0,0 +1,232 @@
+use super::*;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+
+/// Where a document and its fields are in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct DocumentSource {
+    /// Where the document starts, the `---` when there is one
+    pub(super) position: Position,
+    /// Where every key is, by path, e.g. `metadata.name` or `spec.dependsOn[0].name`
+    pub(super) fields: BTreeMap<String, Position>,
+}
+
+impl DocumentSource {
+    fn new(position: Position) -> Self {
+        DocumentSource {
+            position,
+            fields: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for DocumentSource {
+    /// The start of the file
+    fn default() -> Self {
+        DocumentSource::new(Position { line: 1, column: 1 })
+    }
+}
+
+enum Frame {
+    /// `key` is the key of the value being read, `None` while waiting for the next key
+    Mapping { key: Option<String> },
+    /// `index` is the index of the item being read
+    Sequence { index: usize },
+    /// A mapping or a sequence used as a key, what is in it is not recorded
+    ComplexKey,
+}
+
+/// Follows the parser events to know the path of every key.
+#[derive(Default)]
+struct SourceReceiver {
+    documents: Vec<DocumentSource>,
+    stack: Vec<Frame>,
+}
+
+impl SourceReceiver {
+    fn is_key(&self) -> bool {
+        matches!(self.stack.last(), Some(Frame::Mapping { key: None }))
+    }
+
+    /// The path of the node being read.
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for frame in &self.stack {
+            match frame {
+                Frame::Mapping { key: Some(key) } if path.is_empty() => path.push_str(key),
+                Frame::Mapping { key: Some(key) } => {
+                    path.push('.');
+                    path.push_str(key);
+                }
+                Frame::Sequence { index } => path.push_str(&format!("[{index}]")),
+                Frame::Mapping { key: None } | Frame::ComplexKey => {}
+            }
+        }
+        path
+    }
+
+    fn set_key(&mut self, key: String, mark: &Marker) {
+        if let Some(Frame::Mapping { key: current }) = self.stack.last_mut() {
+            *current = Some(key);
+        }
+        let path = self.path();
+        if let Some(document) = self.documents.last_mut() {
+            document.fields.entry(path).or_insert_with(|| mark.into());
+        }
+    }
+
+    /// A value was read, the next node is a key or the next item.
+    fn end_value(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { key }) => *key = None,
+            Some(Frame::Sequence { index }) => *index += 1,
+            Some(Frame::ComplexKey) | None => {}
+        }
+    }
+
+    fn start_node(&mut self, frame: Frame) {
+        if self.is_key() || matches!(self.stack.last(), Some(Frame::ComplexKey)) {
+            self.stack.push(Frame::ComplexKey);
+        } else {
+            self.stack.push(frame);
+        }
+    }
+
+    fn end_node(&mut self, mark: &Marker) {
+        let ended = self.stack.pop();
+        match (ended, self.stack.last()) {
+            (Some(Frame::ComplexKey), Some(Frame::ComplexKey)) => {}
+            (Some(Frame::ComplexKey), _) => self.set_key("?".to_string(), mark),
+            _ => self.end_value(),
+        }
+    }
+}
+
+impl MarkedEventReceiver for SourceReceiver {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        // Documents without a `---` are marked after their first key, they start at their first node
+        if let (
+            Event::Scalar(..) | Event::Alias(_) | Event::MappingStart(_) | Event::SequenceStart(_),
+            Some(document),
+        ) = (&ev, self.documents.last_mut())
+        {
+            document.position = document.position.min(Position::from(&mark));
+        }
+        match ev {
+            Event::DocumentStart => {
+                self.documents
+                    .push(DocumentSource::new(Position::from(&mark)));
+                self.stack.clear();
+            }
+            Event::Scalar(..) | Event::Alias(_)
+                if matches!(self.stack.last(), Some(Frame::ComplexKey)) => {}
+            Event::Scalar(value, ..) if self.is_key() => self.set_key(value, &mark),
+            Event::Alias(_) if self.is_key() => self.set_key("*".to_string(), &mark),
+            Event::Scalar(..) | Event::Alias(_) => self.end_value(),
+            Event::MappingStart(_) => self.start_node(Frame::Mapping { key: None }),
+            Event::SequenceStart(_) => self.start_node(Frame::Sequence { index: 0 }),
+            Event::MappingEnd | Event::SequenceEnd => self.end_node(&mark),
+            _ => {}
+        }
+    }
+}
+
+/// Finds where every document of the file starts and where its fields are.
+/// The documents are in the same order as the ones read by serde_yaml, which uses the same parser.
+/// When the YAML is not valid, only the documents before the error are returned.
+pub(super) fn document_sources(content: &str) -> Vec<DocumentSource> {
+    let mut receiver = SourceReceiver::default();
+    // The documents read before the error are still useful
+    let _ = Parser::new(content.chars()).load(&mut receiver, true);
+    receiver.documents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(content: &str) -> BTreeMap<String, Position> {
+        let mut documents = document_sources(content);
+        assert_eq!(documents.len(), 1);
+        documents.remove(0).fields
+    }
+
+    fn position(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn mappings_in_sequences() {
+        let fields = fields(
+            "\
+metadata:
+  name: apps
+spec:
+  dependsOn:
+    - name: infra
+    - name: monitoring
+      namespace: ops
+",
+        );
+        assert_eq!(fields["metadata.name"], position(2, 3));
+        assert_eq!(fields["spec.dependsOn"], position(4, 3));
+        assert_eq!(fields["spec.dependsOn[0].name"], position(5, 7));
+        assert_eq!(fields["spec.dependsOn[1].name"], position(6, 7));
+        assert_eq!(fields["spec.dependsOn[1].namespace"], position(7, 7));
+    }
+
+    #[test]
+    fn sequences_in_sequences() {
+        let fields = fields(
+            "\
+a:
+  - - x
+    - b: 1
+  - [1, 2]
+  - c: 2
+",
+        );
+        assert_eq!(fields["a[0][1].b"], position(3, 7));
+        // The items after a nested sequence keep counting
+        assert_eq!(fields["a[2].c"], position(5, 5));
+    }
+
+    #[test]
+    fn flow_collections() {
+        let fields = fields("spec: {values: [{name: a}, {name: b}]}\n");
+        assert_eq!(fields["spec.values[1].name"], position(1, 29));
+    }
+
+    #[test]
+    fn complex_keys() {
+        let fields = fields(
+            "\
+? [a, b]
+: value: 1
+? {c: 1}
+: 2
+next: 3
+",
+        );
+        // What is in a complex key is not recorded, the key is `?`
+        assert!(fields.contains_key("?"));
+        assert!(fields.contains_key("?.value"));
+        assert!(!fields.contains_key("a") && !fields.contains_key("c"));
+        assert_eq!(fields["next"], position(5, 1));
+    }
+
+    #[test]
+    fn alias_keys() {
+        let fields = fields("base: &key name\nmap:\n  *key : value\n  other: 1\n");
+        assert!(fields.contains_key("map.*"));
+        assert_eq!(fields["map.other"], position(4, 3));
+    }
+
+    #[test]
+    fn documents() {
+        let documents = document_sources("a: 1\n---\nb:\n  c: 2\n---\n# empty\n");
+        let positions: Vec<Position> = documents.iter().map(|d| d.position).collect();
+        assert_eq!(positions, [position(1, 1), position(2, 1), position(5, 1)]);
+        assert_eq!(documents[1].fields["b.c"], position(4, 3));
+        assert!(documents[2].fields.is_empty());
+    }
+}
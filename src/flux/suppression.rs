@@ -0,0 +1,216 @@
+use super::*;
+
+/// An `# flux-validator: ignore <rule-id> <reason>` comment.
+/// A comment before the first document of a file is about the whole file, otherwise it is about
+/// the document it is in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Suppression {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    /// Position of the document in the file, starting from 0, absent when it is the whole file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    /// Where the comment is
+    position: Position,
+    /// The rule id as written, it may not be a rule
+    rule: String,
+    reason: String,
+}
+
+/// Reads an ignore comment, `None` when the line is not one.
+/// Returns the column of the `#`, the rule id and the reason.
+fn parse_comment(line: &str) -> Option<(usize, &str, &str)> {
+    let comment = line.trim_start();
+    let column = line[..line.len() - comment.len()].chars().count() + 1;
+    let rest = comment
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("flux-validator:")?
+        .trim_start()
+        .strip_prefix("ignore")?;
+    // `ignored`, `ignore-me`, ... are something else
+    if rest.starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+    let rest = rest.trim();
+    let (rule, reason) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some((column, rule, reason.trim()))
+}
+
+/// Finds the ignore comments of a file, only comments on their own line are read.
+/// `documents` are the documents of the file, to know which one every comment is in.
+pub(super) fn find_suppressions(
+    path: &Path,
+    content: &str,
+    documents: &[DocumentSource],
+) -> Vec<Suppression> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let (column, rule, reason) = parse_comment(line)?;
+            let position = Position {
+                line: i + 1,
+                column,
+            };
+            Some(Suppression {
+                path: path.to_path_buf(),
+                index: documents
+                    .iter()
+                    .rposition(|d| d.position.line <= position.line),
+                position,
+                rule: rule.to_string(),
+                reason: reason.to_string(),
+            })
+        })
+        .collect()
+}
+
+impl Suppression {
+    /// Whether the comment ignores the findings of the rule at the location.
+    /// Comments without a reason ignore nothing.
+    pub fn covers(&self, rule: Rule, location: &FindingLocation) -> bool {
+        self.get_rule() == Some(rule)
+            && !self.reason.is_empty()
+            && self.path == *location.get_path()
+            && (self.index.is_none() || self.index == location.get_index())
+    }
+
+    /// Why the comment does not ignore anything, when it is not used.
+    pub(super) fn unused_reason(&self) -> String {
+        match self.get_rule() {
+            _ if self.rule.is_empty() => "ignore comment has no rule id".to_string(),
+            None => format!("ignore comment has an unknown rule `{}`", self.rule),
+            Some(_) if self.reason.is_empty() => format!(
+                "ignore comment for `{}` has no reason, it is not applied",
+                self.rule
+            ),
+            Some(_) => format!(
+                "ignore comment for `{}` does not match any finding",
+                self.rule
+            ),
+        }
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub(super) fn get_path_mut(&mut self) -> &mut PathBuf {
+        &mut self.path
+    }
+
+    pub fn get_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn get_position(&self) -> Position {
+        self.position
+    }
+
+    /// The rule ignored, `None` when the id is not a rule.
+    pub fn get_rule(&self) -> Option<Rule> {
+        Rule::from_id(&self.rule)
+    }
+
+    pub fn get_rule_id(&self) -> &str {
+        &self.rule
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexes(content: &str) -> Vec<(String, Option<usize>)> {
+        let path = Path::new("apps.yaml");
+        find_suppressions(path, content, &document_sources(content))
+            .into_iter()
+            .map(|s| (s.rule, s.index))
+            .collect()
+    }
+
+    #[test]
+    fn parses_comments() {
+        assert_eq!(
+            parse_comment("# flux-validator: ignore unencrypted-secret test fixture"),
+            Some((1, "unencrypted-secret", "test fixture"))
+        );
+        assert_eq!(
+            parse_comment("    #flux-validator:ignore parse-error  not ours  "),
+            Some((5, "parse-error", "not ours"))
+        );
+        assert_eq!(parse_comment("# flux-validator: ignore"), Some((1, "", "")));
+        assert_eq!(
+            parse_comment("# flux-validator: ignored parse-error x"),
+            None
+        );
+        assert_eq!(
+            parse_comment("# flux-validator: ignore-me parse-error x"),
+            None
+        );
+        assert_eq!(parse_comment("# ignore parse-error x"), None);
+        // Only comments on their own line are read
+        assert_eq!(
+            parse_comment("kind: Secret # flux-validator: ignore parse-error x"),
+            None
+        );
+    }
+
+    #[test]
+    fn documents_without_separator() {
+        let content = "\
+# flux-validator: ignore duplicate-resource whole file
+apiVersion: v1
+# flux-validator: ignore parse-error first document
+kind: ConfigMap
+---
+# flux-validator: ignore unencrypted-secret second document
+apiVersion: v1
+kind: Secret
+";
+        assert_eq!(
+            indexes(content),
+            [
+                ("duplicate-resource".to_string(), None),
+                ("parse-error".to_string(), Some(0)),
+                ("unencrypted-secret".to_string(), Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn documents_with_separator() {
+        let content = "\
+# flux-validator: ignore duplicate-resource whole file
+---
+# flux-validator: ignore parse-error first document
+apiVersion: v1
+kind: ConfigMap
+---
+apiVersion: v1
+kind: Secret
+# flux-validator: ignore unencrypted-secret second document
+";
+        assert_eq!(
+            indexes(content),
+            [
+                ("duplicate-resource".to_string(), None),
+                ("parse-error".to_string(), Some(0)),
+                ("unencrypted-secret".to_string(), Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn files_without_documents() {
+        assert_eq!(
+            indexes("# flux-validator: ignore parse-error generated\n"),
+            [("parse-error".to_string(), None)]
+        );
+    }
+}